@@ -1,20 +1,270 @@
-use std::path::{Path, PathBuf};
+use crate::{database::Storage, secret::Secret, signing::RequestSigner};
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+use tokio::sync::OnceCell;
 use typed_builder::TypedBuilder;
 
 #[derive(TypedBuilder, Debug)]
 pub struct Config {
     api_root: String,
-    api_key: Option<String>,
+    #[builder(setter(transform = |api_key: Option<impl Into<Secret>>| api_key.map(Into::into)))]
+    api_key: Option<Secret>,
+    /// How the api key is sent with requests
+    #[builder(default)]
+    auth_scheme: AuthScheme,
+    /// Signs every request, e.g. with [`crate::signing::HmacSigner`]
+    #[builder(default)]
+    signer: Option<Arc<dyn RequestSigner>>,
     db_path: PathBuf,
+    /// Database url selecting the storage backend, e.g. `postgres://host/db`,
+    /// `sqlite://path` or `ndjson://path`. Defaults to SQLite at `db_path`
+    #[builder(default)]
+    db_url: Option<String>,
+    /// Discard responses instead of storing them
+    #[builder(default)]
+    no_store: bool,
+    /// JSON Schema files keyed by request type name (`get`, `last_run`, ...)
+    #[builder(default)]
+    response_schemas: HashMap<String, PathBuf>,
+    /// Retention policy applied automatically after every stored response
+    #[builder(default)]
+    retention: Option<RetentionPolicy>,
+    /// Values scrubbed before responses are stored and requests are logged
+    #[builder(default)]
+    redaction: Redaction,
+    /// Pagination of every request, overriding the one of its endpoint
+    #[builder(default)]
+    pagination: Option<Pagination>,
+    /// Limit of every API and token request from connecting until the body is read
+    #[builder(default)]
+    timeout: Option<Duration>,
+    /// Kept alive across requests, e.g. of a shell session or the server
+    #[builder(default, setter(skip))]
+    connections: Connections,
 }
 impl Config {
     pub fn get_api_root(&self) -> &str {
         &self.api_root
     }
-    pub fn get_api_key(&self) -> Option<&str> {
-        self.api_key.as_deref()
+    pub fn get_api_key(&self) -> Option<&Secret> {
+        self.api_key.as_ref()
+    }
+    pub fn get_auth_scheme(&self) -> &AuthScheme {
+        &self.auth_scheme
+    }
+    pub fn get_signer(&self) -> Option<&dyn RequestSigner> {
+        self.signer.as_deref()
     }
     pub fn get_db_path(&self) -> &Path {
         &self.db_path
     }
+    pub fn get_db_url(&self) -> Option<&str> {
+        self.db_url.as_deref()
+    }
+    pub fn get_no_store(&self) -> bool {
+        self.no_store
+    }
+    pub fn get_response_schema(&self, request_type: &str) -> Option<&Path> {
+        self.response_schemas
+            .get(request_type)
+            .map(PathBuf::as_path)
+    }
+    pub fn get_retention(&self) -> Option<&RetentionPolicy> {
+        self.retention.as_ref()
+    }
+    pub fn get_redaction(&self) -> &Redaction {
+        &self.redaction
+    }
+    pub fn get_pagination(&self) -> Option<&Pagination> {
+        self.pagination.as_ref()
+    }
+    pub fn get_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+    pub(crate) fn get_connections(&self) -> &Connections {
+        &self.connections
+    }
+}
+
+/// HTTP client and storage backend shared by all requests of a config, created on first use
+#[derive(Default)]
+pub struct Connections {
+    client: OnceLock<reqwest::Client>,
+    storage: OnceCell<Arc<dyn Storage>>,
+}
+impl Connections {
+    pub(crate) fn get_client(&self) -> &reqwest::Client {
+        self.client.get_or_init(reqwest::Client::new)
+    }
+    pub(crate) fn get_storage(&self) -> &OnceCell<Arc<dyn Storage>> {
+        &self.storage
+    }
+}
+impl Debug for Connections {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Connections")
+            .field("client", &self.client.get().is_some())
+            .field("storage", &self.storage.initialized())
+            .finish()
+    }
+}
+
+/// How the api key is added to requests
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuthScheme {
+    /// Header with the key as its value, `X-API-Key` by default
+    Header(String),
+    /// `Authorization: Bearer <key>`
+    Bearer,
+    /// `Authorization: Basic`, the key is given as `user:password`
+    Basic,
+    /// Query parameter with the key as its value, redacted from stored urls
+    Query(String),
+    /// `Authorization: Bearer` with a token of the OAuth2 client credentials grant,
+    /// the key is the client secret
+    OAuth2(ClientCredentials),
+}
+impl Default for AuthScheme {
+    fn default() -> Self {
+        AuthScheme::Header("X-API-Key".to_string())
+    }
+}
+
+/// Client of the OAuth2 client credentials grant
+#[derive(TypedBuilder, Debug, Clone, PartialEq)]
+pub struct ClientCredentials {
+    /// Token endpoint, e.g. `https://auth.example.com/oauth2/token`
+    token_url: String,
+    client_id: String,
+    #[builder(default)]
+    scope: Option<String>,
+    /// Also cache tokens in the SQLite database, sharing them between runs
+    #[builder(default)]
+    cache_in_db: bool,
+}
+impl ClientCredentials {
+    pub fn get_token_url(&self) -> &str {
+        &self.token_url
+    }
+    pub fn get_client_id(&self) -> &str {
+        &self.client_id
+    }
+    pub fn get_scope(&self) -> Option<&str> {
+        self.scope.as_deref()
+    }
+    pub fn get_cache_in_db(&self) -> bool {
+        self.cache_in_db
+    }
+}
+
+/// Values replaced by `REDACTED` in stored responses and logs. Credentials of the auth
+/// scheme are always redacted
+#[derive(TypedBuilder, Debug, Default)]
+pub struct Redaction {
+    /// Request headers, case-insensitive
+    #[builder(default)]
+    headers: Vec<String>,
+    /// Query parameters of request urls
+    #[builder(default)]
+    query_params: Vec<String>,
+    /// JSON pointers into response bodies, `*` matches every key or index,
+    /// e.g. `/users/*/token`
+    #[builder(default)]
+    json_pointers: Vec<String>,
+}
+impl Redaction {
+    pub fn get_headers(&self) -> &[String] {
+        &self.headers
+    }
+    pub fn get_query_params(&self) -> &[String] {
+        &self.query_params
+    }
+    pub fn get_json_pointers(&self) -> &[String] {
+        &self.json_pointers
+    }
+}
+
+/// How the url of the next page is found
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageStrategy {
+    /// Follows the `rel="next"` url of `Link` headers
+    Link,
+    /// Sends the cursor found at a JSON pointer of the previous page, e.g. `/next_cursor`,
+    /// as query parameter
+    Cursor { param: String, pointer: String },
+    /// Counts up a query parameter from `start` until a page has no items, or fewer than
+    /// `size` which is sent as `size_param` if set
+    Page {
+        param: String,
+        start: u64,
+        size_param: String,
+        size: Option<u64>,
+    },
+}
+
+/// Following the pages of list-style endpoints, each page is stored as a response
+#[derive(TypedBuilder, Debug, Clone, PartialEq)]
+pub struct Pagination {
+    strategy: PageStrategy,
+    /// Stop after this many pages even if more are available
+    #[builder(default = 100)]
+    max_pages: u32,
+    /// JSON pointer of the items array in pages, e.g. `/items`. The whole body by default
+    #[builder(default)]
+    items_pointer: Option<String>,
+    /// Return the items of all pages merged into one JSON array
+    #[builder(default)]
+    merge: bool,
+}
+impl Pagination {
+    pub fn get_strategy(&self) -> &PageStrategy {
+        &self.strategy
+    }
+    pub fn get_max_pages(&self) -> u32 {
+        self.max_pages
+    }
+    pub fn get_items_pointer(&self) -> Option<&str> {
+        self.items_pointer.as_deref()
+    }
+    pub fn get_merge(&self) -> bool {
+        self.merge
+    }
+}
+
+/// Rules deciding which stored responses are kept when pruning the database.
+/// A response is kept if any of the set rules applies to it.
+#[derive(TypedBuilder, Debug, Clone)]
+pub struct RetentionPolicy {
+    /// Keep responses younger than this many days
+    #[builder(default)]
+    keep_days: Option<u32>,
+    /// Keep this many most recent responses per request type and url
+    #[builder(default)]
+    keep_last: Option<u32>,
+    /// Keep responses whose outcome isn't `Success`
+    #[builder(default = true)]
+    keep_failures: bool,
+    /// Reclaim free space with `VACUUM` after pruning
+    #[builder(default)]
+    vacuum: bool,
+}
+impl RetentionPolicy {
+    pub fn get_keep_days(&self) -> Option<u32> {
+        self.keep_days
+    }
+    pub fn get_keep_last(&self) -> Option<u32> {
+        self.keep_last
+    }
+    pub fn get_keep_failures(&self) -> bool {
+        self.keep_failures
+    }
+    pub fn get_vacuum(&self) -> bool {
+        self.vacuum
+    }
 }
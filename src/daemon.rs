@@ -0,0 +1,311 @@
+use crate::{
+    configuration::Config,
+    database::{self, JobRun},
+    endpoint::{self, Endpoint, Request, RequestBody},
+    error::Error,
+};
+
+use chrono::{DateTime, Local, Utc};
+use cron::Schedule;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::BTreeMap, future::Future, path::Path, str::FromStr, sync::Arc};
+use tokio::task::JoinHandle;
+
+/// File listing the jobs run by the daemon
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScheduleDefinition {
+    #[serde(default)]
+    jobs: Vec<JobDefinition>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JobDefinition {
+    /// Defaults to the endpoint name followed by the parameters
+    name: Option<String>,
+    /// Cron expression in local time, with an optional leading seconds field
+    schedule: String,
+    /// Name of the endpoint, e.g. `last_run`
+    endpoint: String,
+    #[serde(default)]
+    parameters: Vec<String>,
+    json: Option<Value>,
+    form: Option<BTreeMap<String, String>>,
+}
+
+/// Request sent whenever its schedule fires
+#[derive(Debug)]
+pub struct Job {
+    name: String,
+    schedule: Schedule,
+    endpoint: Arc<dyn Endpoint>,
+    values: Vec<String>,
+    body: Option<RequestBody>,
+}
+impl Job {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Next time the job is due strictly after `after`
+    fn next(&self, after: &DateTime<Local>) -> Option<DateTime<Local>> {
+        self.schedule.after(after).next()
+    }
+}
+
+/// Parses a cron expression, standard ones without seconds field run at second 0
+fn schedule(raw: &str) -> Result<Schedule, cron::error::Error> {
+    let raw = raw.trim();
+    match raw.split_whitespace().count() {
+        5 => Schedule::from_str(&format!("0 {raw}")),
+        _ => Schedule::from_str(raw),
+    }
+}
+
+fn parse(path: &Path, raw: &str) -> Result<ScheduleDefinition, Error> {
+    let parse_error = |e: String| Error::ScheduleParse(path.into(), e);
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("yaml" | "yml") => serde_yaml::from_str(raw).map_err(|e| parse_error(e.to_string())),
+        _ => toml::from_str(raw).map_err(|e| parse_error(e.to_string())),
+    }
+}
+
+/// Loads the jobs of a schedule file, validating their endpoints and parameters up front
+pub fn load(endpoints: &[Arc<dyn Endpoint>], path: &Path) -> Result<Vec<Job>, Error> {
+    let raw = std::fs::read_to_string(path).map_err(|e| Error::ScheduleRead(path.into(), e))?;
+    let mut jobs = Vec::new();
+    for definition in parse(path, &raw)?.jobs {
+        let name = definition.name.unwrap_or_else(|| {
+            std::iter::once(definition.endpoint.as_str())
+                .chain(definition.parameters.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(" ")
+        });
+        let invalid = |reason: String| Error::ScheduleInvalidJob(name.clone(), reason);
+
+        let schedule = schedule(&definition.schedule).map_err(|e| invalid(e.to_string()))?;
+        let endpoint =
+            endpoint::find(endpoints, &definition.endpoint).map_err(|e| invalid(e.to_string()))?;
+        let body = match (definition.json, definition.form) {
+            (Some(_), Some(_)) => return Err(invalid("expected either json or form".to_string())),
+            (Some(json), None) => Some(RequestBody::Json(json)),
+            (None, Some(form)) => Some(RequestBody::Form(form.into_iter().collect())),
+            (None, None) => None,
+        };
+        // Fail on start rather than on every run
+        let request = Request::new(endpoint.clone(), definition.parameters.clone())
+            .map_err(|e| invalid(e.to_string()))?;
+        if let Some(body) = &body {
+            request
+                .with_body(body.clone())
+                .map_err(|e| invalid(e.to_string()))?;
+        }
+
+        jobs.push(Job {
+            name,
+            schedule,
+            endpoint,
+            values: definition.parameters,
+            body,
+        });
+    }
+    Ok(jobs)
+}
+
+/// Records the outcome of a run, failures to do so are logged only
+async fn record(config: &Config, job_run: JobRun) {
+    let written = match database::open(config).await {
+        Ok(storage) => storage.write_job_run(&job_run).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = written {
+        tracing::error!(
+            job = job_run.job,
+            "Unable to record job run: {}",
+            e.message()
+        );
+    }
+}
+
+/// Sends the request of a job, storing its responses like any other request
+async fn run_job(config: Arc<Config>, job: Arc<Job>) {
+    let started_at = Utc::now();
+    tracing::info!(job = job.name, "Running job");
+    let result = crate::request(
+        &config,
+        job.endpoint.clone(),
+        job.values.clone(),
+        job.body.clone(),
+    )
+    .await;
+    let (outcome, detail) = match result {
+        Ok(_) => ("Success", None),
+        Err(e @ Error::SchemaViolation(..)) => ("SchemaViolation", Some(e.to_string())),
+        Err(e) => ("Failed", Some(e.message())),
+    };
+    match &detail {
+        Some(detail) => tracing::warn!(job = job.name, outcome, "Job failed: {detail}"),
+        None => tracing::info!(job = job.name, outcome, "Job finished"),
+    }
+
+    let job_run = JobRun {
+        job: job.name.clone(),
+        started_at,
+        finished_at: Utc::now(),
+        outcome: outcome.to_string(),
+        detail,
+    };
+    record(&config, job_run).await;
+}
+
+/// Runs jobs whenever their schedules fire until `shutdown` completes or no job is due
+/// anymore, then waits for runs still in flight.
+/// A job isn't started again while its previous run is in flight, the skipped run is
+/// recorded instead.
+pub async fn run(
+    config: Arc<Config>,
+    jobs: Vec<Job>,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), Error> {
+    let jobs: Vec<Arc<Job>> = jobs.into_iter().map(Arc::new).collect();
+    let mut in_flight: Vec<Option<JoinHandle<()>>> = jobs.iter().map(|_| None).collect();
+    tokio::pin!(shutdown);
+
+    let mut last = Local::now();
+    loop {
+        // Timers may fire early, so never schedule before the previous tick again
+        let now = Local::now().max(last);
+        let Some(due_at) = jobs.iter().filter_map(|job| job.next(&now)).min() else {
+            tracing::info!("No job is due anymore");
+            break;
+        };
+        let wait = (due_at - Local::now()).to_std().unwrap_or_default();
+        tokio::select! {
+            _ = &mut shutdown => {
+                tracing::info!("Shutting down, waiting for running jobs");
+                break;
+            }
+            _ = tokio::time::sleep(wait) => {}
+        }
+        last = due_at;
+
+        for (job, handle) in jobs.iter().zip(&mut in_flight) {
+            if job.next(&now) != Some(due_at) {
+                continue;
+            }
+            if handle.as_ref().is_some_and(|handle| !handle.is_finished()) {
+                tracing::warn!(
+                    job = job.name,
+                    "Skipped, the previous run is still in flight"
+                );
+                let skipped_at = Utc::now();
+                let job_run = JobRun {
+                    job: job.name.clone(),
+                    started_at: skipped_at,
+                    finished_at: skipped_at,
+                    outcome: "Skipped".to_string(),
+                    detail: None,
+                };
+                record(&config, job_run).await;
+                continue;
+            }
+            *handle = Some(tokio::spawn(run_job(config.clone(), job.clone())));
+        }
+    }
+
+    for handle in in_flight.into_iter().flatten() {
+        if let Err(e) = handle.await {
+            tracing::error!("Job panicked: {e}");
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn load_test() {
+    use crate::endpoint::builtin;
+
+    let path = std::env::temp_dir().join("apicommand_schedule_test.toml");
+    std::fs::write(
+        &path,
+        r#"
+[[jobs]]
+schedule = "*/5 * * * *"
+endpoint = "get"
+parameters = ["brand"]
+
+[[jobs]]
+name = "nightly run"
+schedule = "0 30 2 * * *"
+endpoint = "start_run"
+parameters = ["brand", "location"]
+json = { dry_run = true }
+"#,
+    )
+    .unwrap();
+    let jobs = load(&builtin(), &path).unwrap();
+    assert_eq!(jobs[0].get_name(), "get brand");
+    assert_eq!(jobs[1].get_name(), "nightly run");
+    assert_eq!(
+        jobs[1].body,
+        Some(RequestBody::Json(serde_json::json!({ "dry_run": true })))
+    );
+
+    // Five field expressions fire at second 0
+    let after = Local::now();
+    let next = jobs[0].next(&after).unwrap();
+    assert_eq!(next.timestamp() % 300, 0);
+
+    std::fs::write(
+        &path,
+        "[[jobs]]\nschedule = \"* * * * *\"\nendpoint = \"last_run\"\nparameters = [\"brand\"]\n",
+    )
+    .unwrap();
+    assert!(matches!(
+        load(&builtin(), &path),
+        Err(Error::ScheduleInvalidJob(name, _)) if name == "last_run brand"
+    ));
+}
+
+#[tokio::test]
+async fn run_test() {
+    use crate::test_support::stub_server;
+    use std::{sync::atomic::Ordering, time::Duration};
+
+    let (api_root, requests) = stub_server(|_, _| (200, r#"{"ok": true}"#.to_string())).await;
+    let path = std::env::temp_dir().join("apicommand_daemon_test.ndjson");
+    let _ = std::fs::remove_file(&path);
+    let config = Config::builder()
+        .api_root(api_root)
+        .api_key(None::<String>)
+        .db_path(std::path::PathBuf::from("test.sqlite3"))
+        .db_url(Some(format!("ndjson://{}", path.display())))
+        .build();
+    let job = Job {
+        name: "every second".to_string(),
+        schedule: schedule("* * * * * *").unwrap(),
+        endpoint: Arc::new(endpoint::Get),
+        values: vec!["brand".to_string()],
+        body: None,
+    };
+
+    run(
+        Arc::new(config),
+        vec![job],
+        tokio::time::sleep(Duration::from_millis(2500)),
+    )
+    .await
+    .unwrap();
+
+    let lines = std::fs::read_to_string(&path).unwrap();
+    let runs: Vec<Value> = lines
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .filter(|line| line.get("job").is_some())
+        .collect();
+    assert!(runs.len() >= 2);
+    assert!(runs.iter().all(|run| run["outcome"] == "Success"));
+    assert_eq!(requests.load(Ordering::SeqCst), runs.len());
+}
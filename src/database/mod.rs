@@ -0,0 +1,510 @@
+mod ndjson;
+mod noop;
+mod postgres;
+mod sqlite;
+
+use crate::{
+    configuration::{Config, RetentionPolicy},
+    error::Error,
+    network::ApiResponse,
+    oauth::Token,
+};
+use ndjson::NdjsonStorage;
+use noop::NoStorage;
+use postgres::PostgresStorage;
+use sqlite::SqliteStorage;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use sea_query::{
+    Alias, ColumnDef, DeleteStatement, Expr, Iden, InsertStatement, OnConflict, Order,
+    OverStatement, Query, SelectStatement, Table, TableCreateStatement, Value, WindowStatement,
+};
+use sha2::{Digest, Sha256};
+use std::{path::Path, sync::Arc};
+use typed_builder::TypedBuilder;
+
+#[derive(Iden)]
+pub enum Responses {
+    Table,
+    Id,
+    DateTime,
+    RequestType,
+    Url,
+    Data,
+    Outcome,
+    OutcomeDetail,
+    BodyHash,
+    RequestBody,
+}
+
+/// Response bodies addressed by the SHA-256 hash of their content
+#[derive(Iden)]
+pub enum Bodies {
+    Table,
+    Id,
+    Hash,
+    Data,
+}
+
+/// Runs of scheduled jobs, see [`crate::daemon`]
+#[derive(Iden)]
+pub enum JobRuns {
+    Table,
+    Id,
+    Job,
+    StartedAt,
+    FinishedAt,
+    Outcome,
+    Detail,
+}
+
+#[derive(Iden)]
+pub enum Migrations {
+    Table,
+    Version,
+}
+
+/// Response as stored in the `Responses` table together with its body
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct StoredResponse {
+    pub id: i64,
+    pub date_time: DateTime<Utc>,
+    pub request_type: String,
+    pub url: String,
+    pub data: String,
+    pub outcome: String,
+    /// Body sent with the request, if any
+    pub request_body: Option<String>,
+}
+
+/// Outcome of a single run of a scheduled job
+#[derive(Debug, Clone)]
+pub struct JobRun {
+    pub job: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    /// `Success`, `SchemaViolation`, `Failed` or `Skipped` while the previous run is in flight
+    pub outcome: String,
+    /// Error of failed runs
+    pub detail: Option<String>,
+}
+
+/// Number of rows and bytes removed by [`Storage::prune`]
+#[derive(Debug, Default)]
+pub struct PruneReport {
+    pub responses: u64,
+    pub bodies: u64,
+    /// Bytes the database file shrank by, only measured when vacuuming SQLite
+    pub bytes: Option<u64>,
+}
+
+/// Full-text search over stored response bodies
+#[derive(TypedBuilder, Debug)]
+pub struct SearchQuery {
+    /// FTS5 query, e.g. `"store code" AND X*`
+    query: String,
+    /// Only responses of this stored request type, see [`crate::endpoint::Endpoint::stored_name`]
+    #[builder(default)]
+    request_type: Option<String>,
+    #[builder(default)]
+    since: Option<DateTime<Utc>>,
+    #[builder(default)]
+    until: Option<DateTime<Utc>>,
+    #[builder(default = 20)]
+    limit: u64,
+    /// Markers placed around matches in snippets
+    #[builder(default = ("[".to_string(), "]".to_string()))]
+    highlight: (String, String),
+}
+impl SearchQuery {
+    pub fn get_query(&self) -> &str {
+        &self.query
+    }
+    pub fn get_request_type(&self) -> Option<&str> {
+        self.request_type.as_deref()
+    }
+    pub fn get_since(&self) -> Option<&DateTime<Utc>> {
+        self.since.as_ref()
+    }
+    pub fn get_until(&self) -> Option<&DateTime<Utc>> {
+        self.until.as_ref()
+    }
+    pub fn get_limit(&self) -> u64 {
+        self.limit
+    }
+    pub fn get_highlight(&self) -> (&str, &str) {
+        (&self.highlight.0, &self.highlight.1)
+    }
+}
+
+/// Stored response matching a full-text search
+#[derive(sqlx::FromRow, Debug)]
+pub struct SearchResult {
+    pub id: i64,
+    pub date_time: DateTime<Utc>,
+    pub request_type: String,
+    pub url: String,
+    pub outcome: String,
+    pub snippet: String,
+}
+
+/// Backend storing API responses
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Where responses are stored, e.g. the database file, named in errors
+    fn location(&self) -> String;
+    /// Stores a response, its body is only stored once per content hash
+    async fn write(&self, api_response: &ApiResponse) -> Result<(), Error>;
+    /// Records the outcome of a run of a scheduled job
+    async fn write_job_run(&self, job_run: &JobRun) -> Result<(), Error>;
+    /// Reads a single stored response by its id
+    async fn read(&self, id: i64) -> Result<StoredResponse, Error>;
+    /// Reads the most recent stored responses for a stored endpoint name and url,
+    /// newest first
+    async fn latest(
+        &self,
+        request_type: &str,
+        url: &str,
+        limit: u64,
+    ) -> Result<Vec<StoredResponse>, Error>;
+    /// Reads the stored response of the same request preceding `stored`, if any
+    async fn previous(&self, stored: &StoredResponse) -> Result<Option<StoredResponse>, Error>;
+    /// Reads the most recent stored responses, optionally only those of a stored endpoint
+    /// name, newest first
+    async fn history(
+        &self,
+        request_type: Option<&str>,
+        limit: u64,
+    ) -> Result<Vec<StoredResponse>, Error>;
+    /// Distinct stored endpoint names and urls of stored responses
+    async fn requests(&self) -> Result<Vec<(String, String)>, Error>;
+    /// Deletes responses not kept by the retention policy and bodies no longer referenced
+    async fn prune(&self, policy: &RetentionPolicy) -> Result<PruneReport, Error>;
+    /// Searches stored bodies, newest responses first
+    async fn search(&self, query: &SearchQuery) -> Result<Vec<SearchResult>, Error>;
+    /// Reads the cached OAuth2 token of a client, which may have expired. Only SQLite caches
+    /// tokens
+    async fn read_token(&self, _token_url: &str, _client_id: &str) -> Result<Option<Token>, Error> {
+        Ok(None)
+    }
+    /// Caches the OAuth2 token of a client, replacing the previous one
+    async fn write_token(
+        &self,
+        _token_url: &str,
+        _client_id: &str,
+        _token: &Token,
+    ) -> Result<(), Error> {
+        tracing::warn!("OAuth2 tokens are only cached in SQLite databases");
+        Ok(())
+    }
+}
+
+/// Opens the backend selected by the configured database url, defaults to SQLite at the
+/// configured database path. Nothing is opened if storing is disabled
+async fn connect(config: &Config) -> Result<Arc<dyn Storage>, Error> {
+    if config.get_no_store() {
+        return Ok(Arc::new(NoStorage));
+    }
+    match config.get_db_url() {
+        None => Ok(Arc::new(SqliteStorage::open(config.get_db_path()).await?)),
+        Some(url) if url.starts_with("postgres://") || url.starts_with("postgresql://") => {
+            Ok(Arc::new(PostgresStorage::open(url).await?))
+        }
+        Some(url) if url.starts_with("ndjson://") => Ok(Arc::new(NdjsonStorage::open(
+            url.trim_start_matches("ndjson://"),
+        )?)),
+        Some(url) => match sqlite_path(url) {
+            Some(path) => Ok(Arc::new(SqliteStorage::open(path).await?)),
+            None => Err(Error::DatabaseUnsupportedUrl(url.to_string())),
+        },
+    }
+}
+
+/// Backend of the config, connected on first use and shared by later calls
+pub async fn open(config: &Config) -> Result<Arc<dyn Storage>, Error> {
+    config
+        .get_connections()
+        .get_storage()
+        .get_or_try_init(|| connect(config))
+        .await
+        .cloned()
+}
+
+/// Path of a `sqlite://path` or `sqlite:path` url, connection options are set by the backend
+fn sqlite_path(url: &str) -> Option<&Path> {
+    url.strip_prefix("sqlite://")
+        .or_else(|| url.strip_prefix("sqlite:"))
+        .map(|path| Path::new(path.split('?').next().unwrap_or_default()))
+}
+
+/// Converts a date time into the representation used by a backend
+type DateTimeValue = fn(&DateTime<Utc>) -> Value;
+
+fn create_migrations_table() -> TableCreateStatement {
+    type M = Migrations;
+
+    Table::create()
+        .table(M::Table)
+        .if_not_exists()
+        .col(
+            ColumnDef::new(M::Version)
+                .integer()
+                .not_null()
+                .primary_key(),
+        )
+        .to_owned()
+}
+
+fn current_version() -> SelectStatement {
+    type M = Migrations;
+
+    Query::select()
+        .expr(sea_query::Func::max(Expr::col(M::Version)))
+        .from(M::Table)
+        .to_owned()
+}
+
+fn insert_version(version: i64) -> InsertStatement {
+    type M = Migrations;
+
+    Query::insert()
+        .into_table(M::Table)
+        .columns([M::Version])
+        .values_panic([version.into()])
+        .to_owned()
+}
+
+/// Statement storing a body unless identical content is already stored, with its hash
+fn insert_body(data: &str) -> (String, InsertStatement) {
+    type B = Bodies;
+
+    let hash = format!("{:x}", Sha256::digest(data));
+    let statement = Query::insert()
+        .into_table(B::Table)
+        .columns([B::Hash, B::Data])
+        .values_panic([hash.as_str().into(), data.into()])
+        .on_conflict(OnConflict::column(B::Hash).do_nothing().to_owned())
+        .to_owned();
+    (hash, statement)
+}
+
+fn insert_response(
+    api_response: &ApiResponse,
+    body_hash: String,
+    date_time: DateTimeValue,
+) -> InsertStatement {
+    type R = Responses;
+
+    Query::insert()
+        .into_table(R::Table)
+        .columns([
+            R::DateTime,
+            R::RequestType,
+            R::Url,
+            R::BodyHash,
+            R::Outcome,
+            R::OutcomeDetail,
+            R::RequestBody,
+        ])
+        .values_panic([
+            date_time(&api_response.date_time).into(),
+            api_response.request.endpoint().stored_name().into(),
+            api_response.url.to_string().into(),
+            body_hash.into(),
+            api_response.outcome.to_string().into(),
+            api_response.outcome.detail().into(),
+            api_response.request_body.clone().into(),
+        ])
+        .to_owned()
+}
+
+fn insert_job_run(job_run: &JobRun, date_time: DateTimeValue) -> InsertStatement {
+    type J = JobRuns;
+
+    Query::insert()
+        .into_table(J::Table)
+        .columns([J::Job, J::StartedAt, J::FinishedAt, J::Outcome, J::Detail])
+        .values_panic([
+            job_run.job.as_str().into(),
+            date_time(&job_run.started_at).into(),
+            date_time(&job_run.finished_at).into(),
+            job_run.outcome.as_str().into(),
+            job_run.detail.clone().into(),
+        ])
+        .to_owned()
+}
+
+/// Selects the columns of [`StoredResponse`], joining each response with its body
+fn select_responses() -> SelectStatement {
+    type R = Responses;
+    type B = Bodies;
+
+    Query::select()
+        .columns([
+            (R::Table, R::Id),
+            (R::Table, R::DateTime),
+            (R::Table, R::RequestType),
+            (R::Table, R::Url),
+            (R::Table, R::Outcome),
+            (R::Table, R::RequestBody),
+        ])
+        .column((B::Table, B::Data))
+        .from(R::Table)
+        .inner_join(
+            B::Table,
+            Expr::col((R::Table, R::BodyHash)).equals((B::Table, B::Hash)),
+        )
+        .to_owned()
+}
+
+fn select_response(id: i64) -> SelectStatement {
+    type R = Responses;
+
+    select_responses()
+        .and_where(Expr::col((R::Table, R::Id)).eq(id))
+        .to_owned()
+}
+
+fn select_latest(request_type: &str, url: &str, limit: u64) -> SelectStatement {
+    type R = Responses;
+
+    select_responses()
+        .and_where(Expr::col(R::RequestType).eq(request_type))
+        .and_where(Expr::col(R::Url).eq(url))
+        .order_by((R::Table, R::Id), Order::Desc)
+        .limit(limit)
+        .to_owned()
+}
+
+fn select_previous(stored: &StoredResponse) -> SelectStatement {
+    type R = Responses;
+
+    select_latest(&stored.request_type, &stored.url, 1)
+        .and_where(Expr::col((R::Table, R::Id)).lt(stored.id))
+        .to_owned()
+}
+
+fn select_history(request_type: Option<&str>, limit: u64) -> SelectStatement {
+    type R = Responses;
+
+    let mut select = select_responses();
+    if let Some(request_type) = request_type {
+        select.and_where(Expr::col((R::Table, R::RequestType)).eq(request_type));
+    }
+    select
+        .order_by((R::Table, R::Id), Order::Desc)
+        .limit(limit)
+        .to_owned()
+}
+
+fn select_requests() -> SelectStatement {
+    type R = Responses;
+
+    Query::select()
+        .distinct()
+        .columns([R::RequestType, R::Url])
+        .from(R::Table)
+        .to_owned()
+}
+
+/// Statement deleting responses not kept by the policy,
+/// `None` if the policy has no rule keeping responses and nothing is pruned
+fn delete_expired(policy: &RetentionPolicy, date_time: DateTimeValue) -> Option<DeleteStatement> {
+    type R = Responses;
+
+    if policy.get_keep_days().is_none() && policy.get_keep_last().is_none() {
+        return None;
+    }
+
+    // Rank responses per request type and url, newest first
+    let ranked = Query::select()
+        .columns([R::Id, R::DateTime, R::Outcome])
+        .expr_window_as(
+            Expr::cust("ROW_NUMBER()"),
+            WindowStatement::partition_by(R::RequestType)
+                .add_partition_by(Expr::col(R::Url).into())
+                .order_by(R::Id, Order::Desc)
+                .to_owned(),
+            Alias::new("rank"),
+        )
+        .from(R::Table)
+        .to_owned();
+
+    let mut expired = Query::select();
+    expired
+        .column(R::Id)
+        .from_subquery(ranked, Alias::new("ranked"));
+    if let Some(keep_days) = policy.get_keep_days() {
+        let cutoff = Utc::now() - Duration::days(keep_days.into());
+        expired.and_where(Expr::col(R::DateTime).lt(date_time(&cutoff)));
+    }
+    if let Some(keep_last) = policy.get_keep_last() {
+        expired.and_where(Expr::col(Alias::new("rank")).gt(keep_last));
+    }
+    if policy.get_keep_failures() {
+        expired.and_where(Expr::col(R::Outcome).eq("Success"));
+    }
+
+    Some(
+        Query::delete()
+            .from_table(R::Table)
+            .and_where(Expr::col(R::Id).in_subquery(expired))
+            .to_owned(),
+    )
+}
+
+/// Statement deleting bodies no longer referenced by any response
+fn delete_orphan_bodies() -> DeleteStatement {
+    type R = Responses;
+    type B = Bodies;
+
+    Query::delete()
+        .from_table(B::Table)
+        .and_where(
+            Expr::col(B::Hash).not_in_subquery(
+                Query::select()
+                    .column(R::BodyHash)
+                    .from(R::Table)
+                    .and_where(Expr::col(R::BodyHash).is_not_null())
+                    .to_owned(),
+            ),
+        )
+        .to_owned()
+}
+
+/// Adds the request type and time filters of a search
+fn filter_search(select: &mut SelectStatement, query: &SearchQuery, date_time: DateTimeValue) {
+    type R = Responses;
+
+    if let Some(request_type) = query.get_request_type() {
+        select.and_where(Expr::col((R::Table, R::RequestType)).eq(request_type));
+    }
+    if let Some(since) = query.get_since() {
+        select.and_where(Expr::col((R::Table, R::DateTime)).gte(date_time(since)));
+    }
+    if let Some(until) = query.get_until() {
+        select.and_where(Expr::col((R::Table, R::DateTime)).lte(date_time(until)));
+    }
+    select
+        .order_by((R::Table, R::Id), Order::Desc)
+        .limit(query.get_limit());
+}
+
+#[cfg(test)]
+pub(crate) fn test_response(data: &str) -> ApiResponse {
+    use crate::{
+        endpoint::{Get, Request},
+        network::Outcome,
+    };
+
+    ApiResponse {
+        date_time: Utc::now(),
+        request: Request::new(std::sync::Arc::new(Get), vec!["brand".to_string()]).unwrap(),
+        status: reqwest::StatusCode::OK,
+        url: reqwest::Url::parse("http://localhost/get/brand").unwrap(),
+        request_body: None,
+        data: data.to_string(),
+        outcome: Outcome::Success,
+    }
+}
@@ -0,0 +1,262 @@
+#[cfg(test)]
+use super::test_response;
+use super::{JobRun, PruneReport, SearchQuery, SearchResult, Storage, StoredResponse};
+use crate::{configuration::RetentionPolicy, error::Error, network::ApiResponse};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Rotated files kept next to the current one if `max_files` isn't set
+const DEFAULT_MAX_FILES: u32 = 5;
+
+/// Append-only file with one JSON object per response, rotated by size
+pub struct NdjsonStorage {
+    path: PathBuf,
+    /// Rotate once the file would grow beyond this many bytes, never if `None`
+    max_bytes: Option<u64>,
+    /// Number of rotated files `path.1` (newest) to `path.N` (oldest) kept
+    max_files: u32,
+    /// Serializes appends and rotations of this process
+    lock: Mutex<()>,
+}
+impl NdjsonStorage {
+    /// Parses `path?max_bytes=N&max_files=N`, the part of the url after `ndjson://`
+    pub fn open(location: &str) -> Result<Self, Error> {
+        let unsupported = || Error::DatabaseUnsupportedUrl(format!("ndjson://{location}"));
+        let (path, options) = location.split_once('?').unwrap_or((location, ""));
+        if path.is_empty() {
+            return Err(unsupported());
+        }
+
+        let mut storage = Self {
+            path: PathBuf::from(path),
+            max_bytes: None,
+            max_files: DEFAULT_MAX_FILES,
+            lock: Mutex::new(()),
+        };
+        for option in options.split('&').filter(|option| !option.is_empty()) {
+            match option.split_once('=') {
+                Some(("max_bytes", value)) => {
+                    storage.max_bytes = Some(value.parse().map_err(|_| unsupported())?)
+                }
+                Some(("max_files", value)) => {
+                    storage.max_files = value.parse().map_err(|_| unsupported())?
+                }
+                _ => return Err(unsupported()),
+            }
+        }
+        Ok(storage)
+    }
+
+    fn rotated(&self, index: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        path.into()
+    }
+
+    /// Shifts `path` to `path.1`, `path.1` to `path.2` and so on, dropping the oldest file
+    fn rotate(&self) -> Result<(), Error> {
+        let file_error = |path: &Path| {
+            let path = path.to_path_buf();
+            move |error| Error::DatabaseFile(path, error)
+        };
+        if self.max_files == 0 {
+            return fs::remove_file(&self.path).map_err(file_error(&self.path));
+        }
+
+        let oldest = self.rotated(self.max_files);
+        if oldest.exists() {
+            fs::remove_file(&oldest).map_err(file_error(&oldest))?;
+        }
+        for index in (1..self.max_files).rev() {
+            let from = self.rotated(index);
+            if from.exists() {
+                fs::rename(&from, self.rotated(index + 1)).map_err(file_error(&from))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated(1)).map_err(file_error(&self.path))
+    }
+}
+
+/// Single line of the file, bodies that are valid JSON are embedded as is
+fn line(api_response: &ApiResponse) -> String {
+    // Embedded as JSON where possible
+    let embed =
+        |data: &str| serde_json::from_str::<Value>(data).unwrap_or(Value::String(data.to_string()));
+    let mut line = json!({
+        "date_time": api_response
+            .date_time
+            .to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        "request_type": api_response.request.endpoint().stored_name(),
+        "url": api_response.url.as_str(),
+        "status": api_response.status.as_u16(),
+        "outcome": api_response.outcome.to_string(),
+        "outcome_detail": api_response.outcome.detail(),
+        "request_body": api_response.request_body.as_deref().map(embed),
+        "data": embed(&api_response.data),
+    })
+    .to_string();
+    line.push('\n');
+    line
+}
+
+/// Line recording the run of a scheduled job, told apart from responses by its `job` key
+fn job_run_line(job_run: &JobRun) -> String {
+    let timestamp =
+        |date_time: &DateTime<Utc>| date_time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    let mut line = json!({
+        "job": job_run.job,
+        "started_at": timestamp(&job_run.started_at),
+        "finished_at": timestamp(&job_run.finished_at),
+        "outcome": job_run.outcome,
+        "detail": job_run.detail,
+    })
+    .to_string();
+    line.push('\n');
+    line
+}
+
+impl NdjsonStorage {
+    /// Appends a line, rotating the file first if it would grow too large
+    fn append(&self, line: &str) -> Result<(), Error> {
+        let file_error = |error| Error::DatabaseFile(self.path.clone(), error);
+        let _guard = self
+            .lock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if let Some(max_bytes) = self.max_bytes {
+            let size = fs::metadata(&self.path).map_or(0, |metadata| metadata.len());
+            if size > 0 && size + line.len() as u64 > max_bytes {
+                self.rotate()?;
+            }
+        }
+        // A single write per line keeps lines of concurrent processes whole
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .map_err(file_error)
+    }
+}
+
+#[async_trait]
+impl Storage for NdjsonStorage {
+    fn location(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    async fn write(&self, api_response: &ApiResponse) -> Result<(), Error> {
+        self.append(&line(api_response))
+    }
+
+    async fn write_job_run(&self, job_run: &JobRun) -> Result<(), Error> {
+        self.append(&job_run_line(job_run))
+    }
+
+    async fn read(&self, _id: i64) -> Result<StoredResponse, Error> {
+        Err(Error::DatabaseUnsupportedOperation(
+            "NDJSON",
+            "reading responses",
+            self.location(),
+        ))
+    }
+
+    async fn latest(
+        &self,
+        _request_type: &str,
+        _url: &str,
+        _limit: u64,
+    ) -> Result<Vec<StoredResponse>, Error> {
+        Err(Error::DatabaseUnsupportedOperation(
+            "NDJSON",
+            "reading responses",
+            self.location(),
+        ))
+    }
+
+    async fn previous(&self, _stored: &StoredResponse) -> Result<Option<StoredResponse>, Error> {
+        Err(Error::DatabaseUnsupportedOperation(
+            "NDJSON",
+            "reading responses",
+            self.location(),
+        ))
+    }
+
+    async fn history(
+        &self,
+        _request_type: Option<&str>,
+        _limit: u64,
+    ) -> Result<Vec<StoredResponse>, Error> {
+        Err(Error::DatabaseUnsupportedOperation(
+            "NDJSON",
+            "reading responses",
+            self.location(),
+        ))
+    }
+
+    async fn requests(&self) -> Result<Vec<(String, String)>, Error> {
+        Err(Error::DatabaseUnsupportedOperation(
+            "NDJSON",
+            "reading responses",
+            self.location(),
+        ))
+    }
+
+    /// Nothing is pruned, files are limited by rotation instead, see `max_bytes` and
+    /// `max_files`
+    async fn prune(&self, _policy: &RetentionPolicy) -> Result<PruneReport, Error> {
+        Ok(PruneReport::default())
+    }
+
+    async fn search(&self, _query: &SearchQuery) -> Result<Vec<SearchResult>, Error> {
+        Err(Error::DatabaseUnsupportedOperation(
+            "NDJSON",
+            "search",
+            self.location(),
+        ))
+    }
+}
+
+#[tokio::test]
+async fn ndjson_rotation_test() {
+    let directory = std::env::temp_dir().join("apicommand_ndjson_test");
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("responses.ndjson");
+
+    let api_response = test_response(r#"{"brand_id": "brand"}"#);
+    let max_bytes = line(&api_response).len() * 2;
+    let storage = NdjsonStorage::open(&format!(
+        "{}?max_bytes={max_bytes}&max_files=2",
+        path.display()
+    ))
+    .unwrap();
+    for _ in 0..7 {
+        storage.write(&api_response).await.unwrap();
+    }
+
+    // Two lines per file, the oldest file was dropped
+    let lines = |path: PathBuf| fs::read_to_string(path).unwrap().lines().count();
+    assert_eq!(lines(path.clone()), 1);
+    assert_eq!(lines(storage.rotated(1)), 2);
+    assert_eq!(lines(storage.rotated(2)), 2);
+    assert!(!storage.rotated(3).exists());
+
+    // Retention policies apply after every write and must not fail it
+    let policy = RetentionPolicy::builder().keep_days(Some(1)).build();
+    assert_eq!(storage.prune(&policy).await.unwrap().responses, 0);
+
+    let stored: Value = serde_json::from_str(fs::read_to_string(&path).unwrap().trim()).unwrap();
+    assert_eq!(stored["data"]["brand_id"], "brand");
+    assert_eq!(stored["request_type"], "Get");
+    assert!(NdjsonStorage::open("responses.ndjson?rotate=daily").is_err());
+}
@@ -0,0 +1,95 @@
+use super::{JobRun, PruneReport, SearchQuery, SearchResult, Storage, StoredResponse};
+use crate::{configuration::RetentionPolicy, error::Error, network::ApiResponse, oauth::Token};
+
+use async_trait::async_trait;
+
+/// Discards responses, e.g. for quick checks or read-only filesystems
+pub struct NoStorage;
+
+#[async_trait]
+impl Storage for NoStorage {
+    /// Nothing is stored, the option discarding responses is named instead
+    fn location(&self) -> String {
+        "--no_store".to_string()
+    }
+
+    async fn write(&self, _api_response: &ApiResponse) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn write_job_run(&self, _job_run: &JobRun) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn read(&self, _id: i64) -> Result<StoredResponse, Error> {
+        Err(Error::DatabaseUnsupportedOperation(
+            "no-store",
+            "reading responses",
+            self.location(),
+        ))
+    }
+
+    async fn latest(
+        &self,
+        _request_type: &str,
+        _url: &str,
+        _limit: u64,
+    ) -> Result<Vec<StoredResponse>, Error> {
+        Err(Error::DatabaseUnsupportedOperation(
+            "no-store",
+            "reading responses",
+            self.location(),
+        ))
+    }
+
+    async fn previous(&self, _stored: &StoredResponse) -> Result<Option<StoredResponse>, Error> {
+        Err(Error::DatabaseUnsupportedOperation(
+            "no-store",
+            "reading responses",
+            self.location(),
+        ))
+    }
+
+    async fn history(
+        &self,
+        _request_type: Option<&str>,
+        _limit: u64,
+    ) -> Result<Vec<StoredResponse>, Error> {
+        Err(Error::DatabaseUnsupportedOperation(
+            "no-store",
+            "reading responses",
+            self.location(),
+        ))
+    }
+
+    async fn requests(&self) -> Result<Vec<(String, String)>, Error> {
+        Err(Error::DatabaseUnsupportedOperation(
+            "no-store",
+            "reading responses",
+            self.location(),
+        ))
+    }
+
+    /// Nothing is stored, so nothing is pruned
+    async fn prune(&self, _policy: &RetentionPolicy) -> Result<PruneReport, Error> {
+        Ok(PruneReport::default())
+    }
+
+    async fn search(&self, _query: &SearchQuery) -> Result<Vec<SearchResult>, Error> {
+        Err(Error::DatabaseUnsupportedOperation(
+            "no-store",
+            "search",
+            self.location(),
+        ))
+    }
+
+    /// Nothing is stored, so tokens aren't cached either
+    async fn write_token(
+        &self,
+        _token_url: &str,
+        _client_id: &str,
+        _token: &Token,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
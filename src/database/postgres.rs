@@ -0,0 +1,381 @@
+#[cfg(test)]
+use super::test_response;
+use super::{
+    create_migrations_table, current_version, delete_expired, delete_orphan_bodies, filter_search,
+    insert_body, insert_job_run, insert_response, insert_version, select_history, select_latest,
+    select_previous, select_requests, select_response, Bodies, JobRun, JobRuns, PruneReport,
+    Responses, SearchQuery, SearchResult, Storage, StoredResponse,
+};
+use crate::{configuration::RetentionPolicy, error::Error, network::ApiResponse, secret::REDACTED};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sea_query::{Alias, ColumnDef, Expr, Index, PostgresQueryBuilder, Query, Table, Value};
+use sea_query_binder::SqlxBinder;
+use sqlx::PgPool;
+use url::Url;
+
+/// Key of the advisory lock serializing migrations of clients sharing a database
+const MIGRATION_LOCK: i64 = 0x6170_6963_6f6d;
+
+fn date_time_value(date_time: &DateTime<Utc>) -> Value {
+    (*date_time).into()
+}
+
+/// Schema versions, each entry upgrades by one version.
+/// Starts at the schema SQLite reached through its own migrations.
+fn migrations() -> Vec<Vec<String>> {
+    type R = Responses;
+    type B = Bodies;
+
+    vec![
+        // 1: Responses referencing deduplicated bodies with a full-text index
+        vec![
+            Table::create()
+                .table(B::Table)
+                .col(
+                    ColumnDef::new(B::Id)
+                        .big_integer()
+                        .not_null()
+                        .auto_increment()
+                        .primary_key(),
+                )
+                .col(ColumnDef::new(B::Hash).string().not_null().unique_key())
+                .col(ColumnDef::new(B::Data).text().not_null())
+                .build(PostgresQueryBuilder),
+            Table::create()
+                .table(R::Table)
+                .col(
+                    ColumnDef::new(R::Id)
+                        .big_integer()
+                        .not_null()
+                        .auto_increment()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(R::DateTime)
+                        .timestamp_with_time_zone()
+                        .not_null(),
+                )
+                .col(ColumnDef::new(R::RequestType).string().not_null())
+                .col(ColumnDef::new(R::Url).string().not_null())
+                .col(
+                    ColumnDef::new(R::Outcome)
+                        .string()
+                        .not_null()
+                        .default("Success"),
+                )
+                .col(ColumnDef::new(R::OutcomeDetail).text())
+                .col(ColumnDef::new(R::BodyHash).string())
+                .build(PostgresQueryBuilder),
+            Index::create()
+                .name("idx_responses_body_hash")
+                .table(R::Table)
+                .col(R::BodyHash)
+                .build(PostgresQueryBuilder),
+            // sea-query has no support for expression indexes
+            "CREATE INDEX bodies_search ON bodies USING GIN (to_tsvector('simple', data))"
+                .to_string(),
+        ],
+        // 2: Body sent with the request
+        vec![Table::alter()
+            .table(R::Table)
+            .add_column(ColumnDef::new(R::RequestBody).text())
+            .build(PostgresQueryBuilder)],
+        // 3: Runs of scheduled jobs
+        vec![Table::create()
+            .table(JobRuns::Table)
+            .col(
+                ColumnDef::new(JobRuns::Id)
+                    .big_integer()
+                    .not_null()
+                    .auto_increment()
+                    .primary_key(),
+            )
+            .col(ColumnDef::new(JobRuns::Job).string().not_null())
+            .col(
+                ColumnDef::new(JobRuns::StartedAt)
+                    .timestamp_with_time_zone()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(JobRuns::FinishedAt)
+                    .timestamp_with_time_zone()
+                    .not_null(),
+            )
+            .col(ColumnDef::new(JobRuns::Outcome).string().not_null())
+            .col(ColumnDef::new(JobRuns::Detail).text())
+            .build(PostgresQueryBuilder)],
+    ]
+}
+
+async fn migrate(pool: &PgPool) -> sqlx::Result<()> {
+    // Clients sharing the database may start at the same time, the lock makes them
+    // migrate one after another
+    let mut transaction = pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(MIGRATION_LOCK)
+        .execute(&mut *transaction)
+        .await?;
+
+    let table = create_migrations_table().build(PostgresQueryBuilder);
+    sqlx::query(&table).execute(&mut *transaction).await?;
+
+    let (sql, values) = current_version().build_sqlx(PostgresQueryBuilder);
+    let current = sqlx::query_scalar_with::<_, Option<i32>, _>(&sql, values)
+        .fetch_one(&mut *transaction)
+        .await?
+        .map_or(0, i64::from);
+
+    for (version, statements) in (1..).zip(migrations()) {
+        if version <= current {
+            continue;
+        }
+        for statement in statements {
+            sqlx::query(&statement).execute(&mut *transaction).await?;
+        }
+        let (sql, values) = insert_version(version).build_sqlx(PostgresQueryBuilder);
+        sqlx::query_with(&sql, values)
+            .execute(&mut *transaction)
+            .await?;
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Database url safe to show, with the password replaced
+fn location(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(mut parsed) if parsed.password().is_some() => {
+            let _ = parsed.set_password(Some(REDACTED));
+            parsed.to_string()
+        }
+        _ => url.to_string(),
+    }
+}
+
+/// PostgreSQL database, e.g. shared by several clients
+pub struct PostgresStorage {
+    pool: PgPool,
+    /// Redacted url of the database
+    location: String,
+}
+impl PostgresStorage {
+    /// Connects to the database and brings its schema up to date
+    pub async fn open(url: &str) -> Result<Self, Error> {
+        let location = location(url);
+        let error = |operation: &'static str| {
+            let location = location.clone();
+            move |e| Error::DatabaseSqlx(operation.to_string(), location, e)
+        };
+        let pool = PgPool::connect(url).await.map_err(error("open"))?;
+        migrate(&pool).await.map_err(error("migrate"))?;
+        Ok(Self { pool, location })
+    }
+
+    /// Adds the operation and redacted database url to an error
+    fn error(&self, operation: impl Into<String>) -> impl Fn(sqlx::Error) -> Error + '_ {
+        let operation = operation.into();
+        move |e| Error::DatabaseSqlx(operation.clone(), self.location.clone(), e)
+    }
+}
+
+#[async_trait]
+impl Storage for PostgresStorage {
+    fn location(&self) -> String {
+        self.location.clone()
+    }
+
+    async fn write(&self, api_response: &ApiResponse) -> Result<(), Error> {
+        let error = self.error("store response");
+        let (body_hash, statement) = insert_body(&api_response.data);
+        let mut transaction = self.pool.begin().await.map_err(&error)?;
+        let (sql, values) = statement.build_sqlx(PostgresQueryBuilder);
+        sqlx::query_with(&sql, values)
+            .execute(&mut *transaction)
+            .await
+            .map_err(&error)?;
+        let (sql, values) = insert_response(api_response, body_hash, date_time_value)
+            .build_sqlx(PostgresQueryBuilder);
+        sqlx::query_with(&sql, values)
+            .execute(&mut *transaction)
+            .await
+            .map_err(&error)?;
+        transaction.commit().await.map_err(&error)?;
+        Ok(())
+    }
+
+    async fn write_job_run(&self, job_run: &JobRun) -> Result<(), Error> {
+        let (sql, values) =
+            insert_job_run(job_run, date_time_value).build_sqlx(PostgresQueryBuilder);
+        sqlx::query_with(&sql, values)
+            .execute(&self.pool)
+            .await
+            .map_err(self.error(format!("store run of job `{}`", job_run.job)))?;
+        Ok(())
+    }
+
+    async fn read(&self, id: i64) -> Result<StoredResponse, Error> {
+        let (sql, values) = select_response(id).build_sqlx(PostgresQueryBuilder);
+        sqlx::query_as_with::<_, StoredResponse, _>(&sql, values)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(self.error(format!("read response {id}")))?
+            .ok_or_else(|| Error::DatabaseResponseNotFound(id, self.location()))
+    }
+
+    async fn latest(
+        &self,
+        request_type: &str,
+        url: &str,
+        limit: u64,
+    ) -> Result<Vec<StoredResponse>, Error> {
+        let (sql, values) =
+            select_latest(request_type, url, limit).build_sqlx(PostgresQueryBuilder);
+        sqlx::query_as_with::<_, StoredResponse, _>(&sql, values)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(self.error(format!("read latest `{request_type}` responses")))
+    }
+
+    async fn previous(&self, stored: &StoredResponse) -> Result<Option<StoredResponse>, Error> {
+        let (sql, values) = select_previous(stored).build_sqlx(PostgresQueryBuilder);
+        sqlx::query_as_with::<_, StoredResponse, _>(&sql, values)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(self.error(format!("read response preceding {}", stored.id)))
+    }
+
+    async fn history(
+        &self,
+        request_type: Option<&str>,
+        limit: u64,
+    ) -> Result<Vec<StoredResponse>, Error> {
+        let (sql, values) = select_history(request_type, limit).build_sqlx(PostgresQueryBuilder);
+        sqlx::query_as_with::<_, StoredResponse, _>(&sql, values)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(self.error("read history"))
+    }
+
+    async fn requests(&self) -> Result<Vec<(String, String)>, Error> {
+        let (sql, values) = select_requests().build_sqlx(PostgresQueryBuilder);
+        sqlx::query_as_with::<_, (String, String), _>(&sql, values)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(self.error("read stored requests"))
+    }
+
+    /// A plain `VACUUM` makes the space of deleted rows reusable but rarely shrinks the
+    /// files, so no reclaimed bytes are reported
+    async fn prune(&self, policy: &RetentionPolicy) -> Result<PruneReport, Error> {
+        let error = self.error("prune");
+        let mut report = PruneReport::default();
+
+        if let Some(statement) = delete_expired(policy, date_time_value) {
+            let (sql, values) = statement.build_sqlx(PostgresQueryBuilder);
+            report.responses = sqlx::query_with(&sql, values)
+                .execute(&self.pool)
+                .await
+                .map_err(&error)?
+                .rows_affected();
+
+            let (sql, values) = delete_orphan_bodies().build_sqlx(PostgresQueryBuilder);
+            report.bodies = sqlx::query_with(&sql, values)
+                .execute(&self.pool)
+                .await
+                .map_err(&error)?
+                .rows_affected();
+        }
+
+        if policy.get_vacuum() {
+            sqlx::query("VACUUM responses, bodies")
+                .execute(&self.pool)
+                .await
+                .map_err(&error)?;
+        }
+        Ok(report)
+    }
+
+    /// Uses `websearch_to_tsquery` syntax, e.g. `"store code" or X123 -closed`
+    async fn search(&self, query: &SearchQuery) -> Result<Vec<SearchResult>, Error> {
+        type R = Responses;
+        type B = Bodies;
+
+        let (start, end) = query.get_highlight();
+        let options = format!(r#"StartSel="{start}", StopSel="{end}", MaxWords=16, MinWords=4"#);
+        let mut select = Query::select();
+        select
+            .columns([
+                (R::Table, R::Id),
+                (R::Table, R::DateTime),
+                (R::Table, R::RequestType),
+                (R::Table, R::Url),
+                (R::Table, R::Outcome),
+            ])
+            .expr_as(
+                Expr::cust_with_values(
+                    "ts_headline('simple', bodies.data, websearch_to_tsquery('simple', $1), $2)",
+                    [query.get_query(), options.as_str()],
+                ),
+                Alias::new("snippet"),
+            )
+            .from(R::Table)
+            .inner_join(
+                B::Table,
+                Expr::col((R::Table, R::BodyHash)).equals((B::Table, B::Hash)),
+            )
+            .and_where(Expr::cust_with_values(
+                "to_tsvector('simple', bodies.data) @@ websearch_to_tsquery('simple', $1)",
+                [query.get_query()],
+            ));
+        filter_search(&mut select, query, date_time_value);
+
+        let (sql, values) = select.build_sqlx(PostgresQueryBuilder);
+        sqlx::query_as_with::<_, SearchResult, _>(&sql, values)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(self.error("search"))
+    }
+}
+
+/// Runs against the database in `APICOMMAND_TEST_POSTGRES_URL`, e.g.
+/// `postgres://postgres@localhost/apicommand_test`, and is skipped if it isn't set
+#[tokio::test]
+async fn postgres_storage_test() {
+    let Ok(url) = std::env::var("APICOMMAND_TEST_POSTGRES_URL") else {
+        println!("Skipped, APICOMMAND_TEST_POSTGRES_URL is not set");
+        return;
+    };
+    let pool = PgPool::connect(&url).await.unwrap();
+    sqlx::query("DROP TABLE IF EXISTS responses, bodies, job_runs, migrations")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let storage = PostgresStorage::open(&url).await.unwrap();
+    // Opening an up to date database must not reapply migrations
+    let storage = {
+        drop(storage);
+        PostgresStorage::open(&url).await.unwrap()
+    };
+    for data in [r#"{"store": "X 1234"}"#, r#"{"store": "X 1234"}"#, "5678"] {
+        storage.write(&test_response(data)).await.unwrap();
+    }
+    let bodies: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM bodies")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(bodies, 2);
+    assert_eq!(storage.read(3).await.unwrap().data, "5678");
+
+    let query = SearchQuery::builder().query("1234".to_string()).build();
+    let results = storage.search(&query).await.unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results[0].snippet.contains("[1234]"));
+
+    let policy = RetentionPolicy::builder().keep_last(Some(1)).build();
+    let report = storage.prune(&policy).await.unwrap();
+    assert_eq!((report.responses, report.bodies), (2, 1));
+    assert!(storage.search(&query).await.unwrap().is_empty());
+}
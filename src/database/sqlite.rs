@@ -0,0 +1,636 @@
+#[cfg(test)]
+use super::test_response;
+use super::{
+    create_migrations_table, current_version, delete_expired, delete_orphan_bodies, filter_search,
+    insert_body, insert_job_run, insert_response, insert_version, select_history, select_latest,
+    select_previous, select_requests, select_response, Bodies, JobRun, JobRuns, PruneReport,
+    Responses, SearchQuery, SearchResult, Storage, StoredResponse,
+};
+use crate::{configuration::RetentionPolicy, error::Error, network::ApiResponse, oauth::Token};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sea_query::{
+    Alias, ColumnDef, Expr, Iden, Index, OnConflict, Order, Query, SqliteQueryBuilder, Table, Value,
+};
+use sea_query_binder::SqlxBinder;
+use sqlx::{sqlite::SqliteQueryResult, SqliteConnection, SqlitePool};
+use std::path::{Path, PathBuf};
+
+/// FTS5 index over `Bodies.Data`, kept in sync by triggers on `Bodies`
+#[derive(Iden)]
+pub enum BodiesSearch {
+    Table,
+}
+
+/// OAuth2 access tokens shared between runs
+#[derive(Iden)]
+pub enum OauthTokens {
+    Table,
+    TokenUrl,
+    ClientId,
+    AccessToken,
+    ExpiresAt,
+}
+
+/// Date times are stored as RFC 3339 text, which sorts chronologically
+fn date_time_value(date_time: &DateTime<Utc>) -> Value {
+    date_time
+        .to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+        .into()
+}
+
+async fn connect(path: &Path) -> sqlx::Result<SqlitePool> {
+    const PREFIX: &str = "sqlite://";
+    const POSTFIX: &str = "?mode=rwc";
+    let url: String = format!("{}{}{}", PREFIX, path.to_string_lossy(), POSTFIX);
+
+    SqlitePool::connect(&url).await
+}
+async fn create_table(pool: &SqlitePool) -> sqlx::Result<SqliteQueryResult> {
+    type R = Responses;
+
+    let table = Table::create()
+        .table(R::Table)
+        .if_not_exists()
+        .col(ColumnDef::new(R::Id).integer().not_null().primary_key())
+        .col(ColumnDef::new(R::DateTime).date_time().not_null())
+        .col(ColumnDef::new(R::RequestType).string().not_null())
+        .col(ColumnDef::new(R::Url).string().not_null())
+        .col(ColumnDef::new(R::Data).string().not_null())
+        .build(SqliteQueryBuilder);
+
+    sqlx::query(&table).execute(pool).await
+}
+/// Single step of a migration
+enum Step {
+    Statement(String),
+    /// Moves `Responses.Data` into `Bodies`, see [`move_bodies`]
+    MoveBodies,
+}
+
+/// Schema changes on top of the initial `Responses` table, each entry upgrades by one version
+fn migrations() -> Vec<Vec<Step>> {
+    type R = Responses;
+    type B = Bodies;
+
+    vec![
+        // 1: Outcome of checking the response, e.g. schema violations
+        vec![
+            Step::Statement(
+                Table::alter()
+                    .table(R::Table)
+                    .add_column(
+                        ColumnDef::new(R::Outcome)
+                            .string()
+                            .not_null()
+                            .default("Success"),
+                    )
+                    .build(SqliteQueryBuilder),
+            ),
+            Step::Statement(
+                Table::alter()
+                    .table(R::Table)
+                    .add_column(ColumnDef::new(R::OutcomeDetail).string())
+                    .build(SqliteQueryBuilder),
+            ),
+        ],
+        // 2: Deduplicate response bodies by content hash
+        vec![
+            Step::Statement(
+                Table::create()
+                    .table(B::Table)
+                    .col(ColumnDef::new(B::Hash).string().not_null().primary_key())
+                    .col(ColumnDef::new(B::Data).string().not_null())
+                    .build(SqliteQueryBuilder),
+            ),
+            Step::Statement(
+                Table::alter()
+                    .table(R::Table)
+                    .add_column(ColumnDef::new(R::BodyHash).string())
+                    .build(SqliteQueryBuilder),
+            ),
+            Step::Statement(
+                Index::create()
+                    .name("idx_responses_body_hash")
+                    .table(R::Table)
+                    .col(R::BodyHash)
+                    .build(SqliteQueryBuilder),
+            ),
+            Step::MoveBodies,
+            Step::Statement(
+                Table::alter()
+                    .table(R::Table)
+                    .drop_column(R::Data)
+                    .build(SqliteQueryBuilder),
+            ),
+        ],
+        // 3: Full-text search over bodies. The index references bodies by an explicit
+        // integer key, since `VACUUM` may renumber implicit rowids
+        vec![
+            Step::Statement(
+                Table::create()
+                    .table(Alias::new("bodies_rebuild"))
+                    .col(ColumnDef::new(B::Id).integer().not_null().primary_key())
+                    .col(ColumnDef::new(B::Hash).string().not_null().unique_key())
+                    .col(ColumnDef::new(B::Data).string().not_null())
+                    .build(SqliteQueryBuilder),
+            ),
+            Step::Statement(
+                Query::insert()
+                    .into_table(Alias::new("bodies_rebuild"))
+                    .columns([B::Hash, B::Data])
+                    .select_from(
+                        Query::select()
+                            .columns([B::Hash, B::Data])
+                            .from(B::Table)
+                            .to_owned(),
+                    )
+                    .expect("Column count matches")
+                    .to_string(SqliteQueryBuilder),
+            ),
+            Step::Statement(Table::drop().table(B::Table).build(SqliteQueryBuilder)),
+            Step::Statement(
+                Table::rename()
+                    .table(Alias::new("bodies_rebuild"), B::Table)
+                    .build(SqliteQueryBuilder),
+            ),
+            // sea-query has no support for virtual tables and triggers
+            Step::Statement(
+                "CREATE VIRTUAL TABLE bodies_search USING fts5(data, content='bodies', content_rowid='id')"
+                    .to_string(),
+            ),
+            Step::Statement(
+                "CREATE TRIGGER bodies_search_insert AFTER INSERT ON bodies BEGIN \
+                 INSERT INTO bodies_search (rowid, data) VALUES (new.id, new.data); END"
+                    .to_string(),
+            ),
+            Step::Statement(
+                "CREATE TRIGGER bodies_search_delete AFTER DELETE ON bodies BEGIN \
+                 INSERT INTO bodies_search (bodies_search, rowid, data) \
+                 VALUES ('delete', old.id, old.data); END"
+                    .to_string(),
+            ),
+            Step::Statement(
+                "INSERT INTO bodies_search (bodies_search) VALUES ('rebuild')".to_string(),
+            ),
+        ],
+        // 4: OAuth2 token cache
+        vec![Step::Statement(
+            Table::create()
+                .table(OauthTokens::Table)
+                .col(ColumnDef::new(OauthTokens::TokenUrl).string().not_null())
+                .col(ColumnDef::new(OauthTokens::ClientId).string().not_null())
+                .col(ColumnDef::new(OauthTokens::AccessToken).string().not_null())
+                .col(ColumnDef::new(OauthTokens::ExpiresAt).date_time().not_null())
+                .primary_key(
+                    Index::create()
+                        .col(OauthTokens::TokenUrl)
+                        .col(OauthTokens::ClientId),
+                )
+                .build(SqliteQueryBuilder),
+        )],
+        // 5: Body sent with the request
+        vec![Step::Statement(
+            Table::alter()
+                .table(R::Table)
+                .add_column(ColumnDef::new(R::RequestBody).string())
+                .build(SqliteQueryBuilder),
+        )],
+        // 6: Runs of scheduled jobs
+        vec![Step::Statement(
+            Table::create()
+                .table(JobRuns::Table)
+                .col(ColumnDef::new(JobRuns::Id).integer().not_null().primary_key())
+                .col(ColumnDef::new(JobRuns::Job).string().not_null())
+                .col(ColumnDef::new(JobRuns::StartedAt).date_time().not_null())
+                .col(ColumnDef::new(JobRuns::FinishedAt).date_time().not_null())
+                .col(ColumnDef::new(JobRuns::Outcome).string().not_null())
+                .col(ColumnDef::new(JobRuns::Detail).string())
+                .build(SqliteQueryBuilder),
+        )],
+    ]
+}
+/// Stores a body unless identical content is already stored and returns its hash
+async fn store_body(connection: &mut SqliteConnection, data: &str) -> sqlx::Result<String> {
+    let (hash, statement) = insert_body(data);
+    let (sql, values) = statement.build_sqlx(SqliteQueryBuilder);
+    sqlx::query_with(&sql, values).execute(connection).await?;
+    Ok(hash)
+}
+/// Moves bodies of existing responses into `Bodies`, in batches to limit memory usage
+async fn move_bodies(connection: &mut SqliteConnection) -> sqlx::Result<()> {
+    type R = Responses;
+    const BATCH: u64 = 1000;
+
+    let mut last_id = 0;
+    loop {
+        let (sql, values) = Query::select()
+            .columns([R::Id, R::Data])
+            .from(R::Table)
+            .and_where(Expr::col(R::Id).gt(last_id))
+            .order_by(R::Id, Order::Asc)
+            .limit(BATCH)
+            .build_sqlx(SqliteQueryBuilder);
+        let rows = sqlx::query_as_with::<_, (i64, String), _>(&sql, values)
+            .fetch_all(&mut *connection)
+            .await?;
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        for (id, data) in rows {
+            let hash = store_body(connection, &data).await?;
+            let (sql, values) = Query::update()
+                .table(R::Table)
+                .value(R::BodyHash, hash)
+                .and_where(Expr::col(R::Id).eq(id))
+                .build_sqlx(SqliteQueryBuilder);
+            sqlx::query_with(&sql, values)
+                .execute(&mut *connection)
+                .await?;
+            last_id = id;
+        }
+    }
+}
+async fn migrate(pool: &SqlitePool) -> sqlx::Result<()> {
+    let table = create_migrations_table().build(SqliteQueryBuilder);
+    sqlx::query(&table).execute(pool).await?;
+
+    let (sql, values) = current_version().build_sqlx(SqliteQueryBuilder);
+    let current = sqlx::query_scalar_with::<_, Option<i64>, _>(&sql, values)
+        .fetch_one(pool)
+        .await?
+        .unwrap_or(0);
+
+    for (version, steps) in (1..).zip(migrations()) {
+        if version <= current {
+            continue;
+        }
+        // Apply each version atomically so a failure can be retried
+        let mut transaction = pool.begin().await?;
+        for step in steps {
+            match step {
+                Step::Statement(statement) => {
+                    sqlx::query(&statement).execute(&mut *transaction).await?;
+                }
+                Step::MoveBodies => move_bodies(&mut transaction).await?,
+            }
+        }
+        let (sql, values) = insert_version(version).build_sqlx(SqliteQueryBuilder);
+        sqlx::query_with(&sql, values)
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+    }
+    Ok(())
+}
+/// SQLite database file, the default backend
+pub struct SqliteStorage {
+    pool: SqlitePool,
+    path: PathBuf,
+}
+impl SqliteStorage {
+    /// Connects to the database and brings its schema up to date
+    pub async fn open(path: &Path) -> Result<Self, Error> {
+        let error = |operation: &'static str| {
+            move |e| Error::DatabaseSqlx(operation.to_string(), path.display().to_string(), e)
+        };
+        let pool = connect(path).await.map_err(error("open"))?;
+        create_table(&pool).await.map_err(error("open"))?;
+        migrate(&pool).await.map_err(error("migrate"))?;
+        Ok(Self {
+            pool,
+            path: path.into(),
+        })
+    }
+
+    /// Adds the operation and database path to an error
+    fn error(&self, operation: impl Into<String>) -> impl Fn(sqlx::Error) -> Error + '_ {
+        let operation = operation.into();
+        move |e| Error::DatabaseSqlx(operation.clone(), self.path.display().to_string(), e)
+    }
+
+    fn size(&self) -> Result<u64, Error> {
+        std::fs::metadata(&self.path)
+            .map(|metadata| metadata.len())
+            .map_err(|e| Error::DatabaseFile(self.path.clone(), e))
+    }
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    fn location(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    async fn write(&self, api_response: &ApiResponse) -> Result<(), Error> {
+        let error = self.error("store response");
+        let mut transaction = self.pool.begin().await.map_err(&error)?;
+        let body_hash = store_body(&mut transaction, &api_response.data)
+            .await
+            .map_err(&error)?;
+        let (sql, values) = insert_response(api_response, body_hash, date_time_value)
+            .build_sqlx(SqliteQueryBuilder);
+        sqlx::query_with(&sql, values)
+            .execute(&mut *transaction)
+            .await
+            .map_err(&error)?;
+        transaction.commit().await.map_err(&error)?;
+        Ok(())
+    }
+
+    async fn write_job_run(&self, job_run: &JobRun) -> Result<(), Error> {
+        let (sql, values) = insert_job_run(job_run, date_time_value).build_sqlx(SqliteQueryBuilder);
+        sqlx::query_with(&sql, values)
+            .execute(&self.pool)
+            .await
+            .map_err(self.error(format!("store run of job `{}`", job_run.job)))?;
+        Ok(())
+    }
+
+    async fn read(&self, id: i64) -> Result<StoredResponse, Error> {
+        let (sql, values) = select_response(id).build_sqlx(SqliteQueryBuilder);
+        sqlx::query_as_with::<_, StoredResponse, _>(&sql, values)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(self.error(format!("read response {id}")))?
+            .ok_or_else(|| Error::DatabaseResponseNotFound(id, self.location()))
+    }
+
+    async fn latest(
+        &self,
+        request_type: &str,
+        url: &str,
+        limit: u64,
+    ) -> Result<Vec<StoredResponse>, Error> {
+        let (sql, values) = select_latest(request_type, url, limit).build_sqlx(SqliteQueryBuilder);
+        sqlx::query_as_with::<_, StoredResponse, _>(&sql, values)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(self.error(format!("read latest `{request_type}` responses")))
+    }
+
+    async fn previous(&self, stored: &StoredResponse) -> Result<Option<StoredResponse>, Error> {
+        let (sql, values) = select_previous(stored).build_sqlx(SqliteQueryBuilder);
+        sqlx::query_as_with::<_, StoredResponse, _>(&sql, values)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(self.error(format!("read response preceding {}", stored.id)))
+    }
+
+    async fn history(
+        &self,
+        request_type: Option<&str>,
+        limit: u64,
+    ) -> Result<Vec<StoredResponse>, Error> {
+        let (sql, values) = select_history(request_type, limit).build_sqlx(SqliteQueryBuilder);
+        sqlx::query_as_with::<_, StoredResponse, _>(&sql, values)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(self.error("read history"))
+    }
+
+    async fn requests(&self) -> Result<Vec<(String, String)>, Error> {
+        let (sql, values) = select_requests().build_sqlx(SqliteQueryBuilder);
+        sqlx::query_as_with::<_, (String, String), _>(&sql, values)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(self.error("read stored requests"))
+    }
+
+    async fn prune(&self, policy: &RetentionPolicy) -> Result<PruneReport, Error> {
+        let error = self.error("prune");
+        let mut report = PruneReport::default();
+
+        if let Some(statement) = delete_expired(policy, date_time_value) {
+            let (sql, values) = statement.build_sqlx(SqliteQueryBuilder);
+            report.responses = sqlx::query_with(&sql, values)
+                .execute(&self.pool)
+                .await
+                .map_err(&error)?
+                .rows_affected();
+
+            let (sql, values) = delete_orphan_bodies().build_sqlx(SqliteQueryBuilder);
+            report.bodies = sqlx::query_with(&sql, values)
+                .execute(&self.pool)
+                .await
+                .map_err(&error)?
+                .rows_affected();
+        }
+
+        if policy.get_vacuum() {
+            // Deleted rows only free pages inside the file, so the size before vacuuming
+            // includes them
+            let size_before = self.size()?;
+            sqlx::query("VACUUM")
+                .execute(&self.pool)
+                .await
+                .map_err(&error)?;
+            report.bytes = Some(size_before.saturating_sub(self.size()?));
+        }
+        Ok(report)
+    }
+
+    async fn read_token(&self, token_url: &str, client_id: &str) -> Result<Option<Token>, Error> {
+        type T = OauthTokens;
+
+        let (sql, values) = Query::select()
+            .columns([T::AccessToken, T::ExpiresAt])
+            .from(T::Table)
+            .and_where(Expr::col(T::TokenUrl).eq(token_url))
+            .and_where(Expr::col(T::ClientId).eq(client_id))
+            .build_sqlx(SqliteQueryBuilder);
+        sqlx::query_as_with::<_, Token, _>(&sql, values)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(self.error("read token"))
+    }
+
+    async fn write_token(
+        &self,
+        token_url: &str,
+        client_id: &str,
+        token: &Token,
+    ) -> Result<(), Error> {
+        type T = OauthTokens;
+
+        let (sql, values) = Query::insert()
+            .into_table(T::Table)
+            .columns([T::TokenUrl, T::ClientId, T::AccessToken, T::ExpiresAt])
+            .values_panic([
+                token_url.into(),
+                client_id.into(),
+                token.access_token.as_str().into(),
+                date_time_value(&token.expires_at).into(),
+            ])
+            .on_conflict(
+                OnConflict::columns([T::TokenUrl, T::ClientId])
+                    .update_columns([T::AccessToken, T::ExpiresAt])
+                    .to_owned(),
+            )
+            .build_sqlx(SqliteQueryBuilder);
+        sqlx::query_with(&sql, values)
+            .execute(&self.pool)
+            .await
+            .map_err(self.error("write token"))?;
+        Ok(())
+    }
+
+    /// Uses FTS5 query syntax
+    async fn search(&self, query: &SearchQuery) -> Result<Vec<SearchResult>, Error> {
+        type R = Responses;
+        type B = Bodies;
+        type S = BodiesSearch;
+
+        let (start, end) = query.get_highlight();
+        let mut select = Query::select();
+        select
+            .columns([
+                (R::Table, R::Id),
+                (R::Table, R::DateTime),
+                (R::Table, R::RequestType),
+                (R::Table, R::Url),
+                (R::Table, R::Outcome),
+            ])
+            .expr_as(
+                Expr::cust_with_values("snippet(bodies_search, 0, ?, ?, '...', 16)", [start, end]),
+                Alias::new("snippet"),
+            )
+            .from(S::Table)
+            .inner_join(
+                B::Table,
+                Expr::col((B::Table, B::Id)).equals((S::Table, Alias::new("rowid"))),
+            )
+            .inner_join(
+                R::Table,
+                Expr::col((R::Table, R::BodyHash)).equals((B::Table, B::Hash)),
+            )
+            .and_where(Expr::cust_with_values(
+                "bodies_search MATCH ?",
+                [query.get_query()],
+            ));
+        filter_search(&mut select, query, date_time_value);
+
+        let (sql, values) = select.build_sqlx(SqliteQueryBuilder);
+        sqlx::query_as_with::<_, SearchResult, _>(&sql, values)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| match &e {
+                // The query is only parsed when it's run
+                sqlx::Error::Database(database) if database.message().starts_with("fts5:") => {
+                    Error::ValidateInvalidSearchQuery(
+                        query.get_query().to_string(),
+                        database.message().to_string(),
+                    )
+                }
+                _ => self.error("search")(e),
+            })
+    }
+}
+
+#[tokio::test]
+async fn migrate_test() {
+    let path = std::env::temp_dir().join("apicommand_migrate_test.sqlite3");
+    let _ = std::fs::remove_file(&path);
+    let pool = connect(&path).await.unwrap();
+    create_table(&pool).await.unwrap();
+
+    // Running twice must not reapply migrations
+    migrate(&pool).await.unwrap();
+    migrate(&pool).await.unwrap();
+
+    let versions: Vec<i64> = sqlx::query_scalar("SELECT version FROM migrations")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(versions.len(), migrations().len());
+
+    let outcome: String = sqlx::query_scalar(
+        "SELECT dflt_value FROM pragma_table_info('responses') WHERE name = 'outcome'",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(outcome, "'Success'");
+}
+
+#[cfg(test)]
+async fn test_storage(name: &str) -> SqliteStorage {
+    let path = std::env::temp_dir().join(name);
+    let _ = std::fs::remove_file(&path);
+    SqliteStorage::open(&path).await.unwrap()
+}
+
+#[tokio::test]
+async fn insert_deduplicates_bodies_test() {
+    let storage = test_storage("apicommand_insert_test.sqlite3").await;
+    let api_response = test_response(r#"{"brand_id": "brand"}"#);
+    storage.write(&api_response).await.unwrap();
+    storage.write(&api_response).await.unwrap();
+
+    let count = |sql| sqlx::query_scalar::<_, i64>(sql).fetch_one(&storage.pool);
+    assert_eq!(count("SELECT COUNT(*) FROM responses").await.unwrap(), 2);
+    assert_eq!(count("SELECT COUNT(*) FROM bodies").await.unwrap(), 1);
+    assert_eq!(storage.read(2).await.unwrap().data, api_response.data);
+
+    let mut api_response = api_response;
+    api_response.request_body = Some(r#"{"force":true}"#.to_string());
+    storage.write(&api_response).await.unwrap();
+    assert_eq!(
+        storage.read(3).await.unwrap().request_body,
+        api_response.request_body
+    );
+}
+
+#[tokio::test]
+async fn prune_test() {
+    let storage = test_storage("apicommand_prune_test.sqlite3").await;
+    for data in ["1", "2", "3"] {
+        storage.write(&test_response(data)).await.unwrap();
+    }
+
+    // Recent responses are kept by age
+    let policy = RetentionPolicy::builder().keep_days(Some(1)).build();
+    assert_eq!(storage.prune(&policy).await.unwrap().responses, 0);
+
+    let policy = RetentionPolicy::builder().keep_last(Some(1)).build();
+    let report = storage.prune(&policy).await.unwrap();
+    assert_eq!((report.responses, report.bodies), (2, 2));
+    assert_eq!(report.bytes, None);
+    assert_eq!(storage.read(3).await.unwrap().data, "3");
+
+    let policy = RetentionPolicy::builder().vacuum(true).build();
+    assert!(storage.prune(&policy).await.unwrap().bytes.is_some());
+}
+
+#[tokio::test]
+async fn search_test() {
+    let storage = test_storage("apicommand_search_test.sqlite3").await;
+    storage
+        .write(&test_response(r#"{"store": "X-1234"}"#))
+        .await
+        .unwrap();
+    storage
+        .write(&test_response(r#"{"store": "Y-5678"}"#))
+        .await
+        .unwrap();
+
+    let query = SearchQuery::builder().query("5678".to_string()).build();
+    let results = storage.search(&query).await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].id, 2);
+    assert!(results[0].snippet.contains("[5678]"));
+
+    // Pruned bodies are removed from the index
+    let policy = RetentionPolicy::builder().keep_last(Some(1)).build();
+    storage.prune(&policy).await.unwrap();
+    let query = SearchQuery::builder().query("1234".to_string()).build();
+    assert!(storage.search(&query).await.unwrap().is_empty());
+
+    // Syntax errors are mistakes in the query, not database failures
+    let query = SearchQuery::builder().query("AND 1234".to_string()).build();
+    let error = storage.search(&query).await.unwrap_err();
+    assert!(matches!(error, Error::ValidateInvalidSearchQuery(..)));
+    assert_eq!(error.family().exit_code(), 3);
+}
@@ -0,0 +1,536 @@
+use crate::{
+    configuration::{PageStrategy, Pagination},
+    endpoint::{Endpoint, Parameter, ResponseFormat},
+    error::Error,
+};
+
+use regex::Regex;
+use reqwest::{
+    header::{HeaderName, HeaderValue},
+    Method,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::BTreeMap, path::Path, sync::Arc};
+
+/// File listing endpoints in addition to the built-in ones
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Definitions {
+    #[serde(default)]
+    endpoints: Vec<EndpointDefinition>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct EndpointDefinition {
+    pub(crate) name: String,
+    pub(crate) stored_name: Option<String>,
+    #[serde(default)]
+    pub(crate) about: String,
+    pub(crate) alias: Option<String>,
+    #[serde(default = "default_method")]
+    pub(crate) method: String,
+    pub(crate) path: String,
+    #[serde(default)]
+    pub(crate) parameters: Vec<ParameterDefinition>,
+    #[serde(default)]
+    pub(crate) headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub(crate) response_format: FormatDefinition,
+    /// JSON Schema of successful responses, only derived from OpenAPI specifications
+    #[serde(skip)]
+    pub(crate) response_schema: Option<Value>,
+    pub(crate) pagination: Option<PaginationDefinition>,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct PaginationDefinition {
+    #[serde(flatten)]
+    strategy: StrategyDefinition,
+    #[serde(default = "default_max_pages")]
+    max_pages: u32,
+    items: Option<String>,
+    #[serde(default)]
+    merge: bool,
+}
+
+fn default_max_pages() -> u32 {
+    100
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "strategy", rename_all = "lowercase")]
+enum StrategyDefinition {
+    Link,
+    Cursor {
+        #[serde(default = "default_cursor_param")]
+        param: String,
+        #[serde(default = "default_cursor_pointer")]
+        pointer: String,
+    },
+    Page {
+        #[serde(default = "default_page_param")]
+        param: String,
+        #[serde(default = "default_page_start")]
+        start: u64,
+        #[serde(default = "default_size_param")]
+        size_param: String,
+        size: Option<u64>,
+    },
+}
+
+fn default_cursor_param() -> String {
+    "cursor".to_string()
+}
+fn default_cursor_pointer() -> String {
+    "/next_cursor".to_string()
+}
+fn default_page_param() -> String {
+    "page".to_string()
+}
+fn default_page_start() -> u64 {
+    1
+}
+fn default_size_param() -> String {
+    "limit".to_string()
+}
+
+impl From<PaginationDefinition> for Pagination {
+    fn from(definition: PaginationDefinition) -> Self {
+        let strategy = match definition.strategy {
+            StrategyDefinition::Link => PageStrategy::Link,
+            StrategyDefinition::Cursor { param, pointer } => {
+                PageStrategy::Cursor { param, pointer }
+            }
+            StrategyDefinition::Page {
+                param,
+                start,
+                size_param,
+                size,
+            } => PageStrategy::Page {
+                param,
+                start,
+                size_param,
+                size,
+            },
+        };
+        Pagination::builder()
+            .strategy(strategy)
+            .max_pages(definition.max_pages)
+            .items_pointer(definition.items)
+            .merge(definition.merge)
+            .build()
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum FormatDefinition {
+    #[default]
+    Json,
+    Text,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ParameterType {
+    #[default]
+    String,
+    Integer,
+    Number,
+    /// Unix timestamp in milliseconds
+    Timestamp,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ParameterDefinition {
+    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) help: String,
+    #[serde(default, rename = "type")]
+    pub(crate) kind: ParameterType,
+    pub(crate) pattern: Option<String>,
+    /// Lets the pattern match anywhere in the value like in JSON Schema, instead of the
+    /// whole value
+    #[serde(skip)]
+    pub(crate) search_pattern: bool,
+    pub(crate) min_length: Option<usize>,
+    pub(crate) max_length: Option<usize>,
+    pub(crate) min: Option<f64>,
+    pub(crate) max: Option<f64>,
+    /// Allowed values, any value if empty
+    #[serde(default)]
+    pub(crate) one_of: Vec<String>,
+}
+
+/// Parameter of a defined endpoint with its validation rules
+#[derive(Debug)]
+struct DefinedParameter {
+    definition: ParameterDefinition,
+    pattern: Option<Regex>,
+}
+impl DefinedParameter {
+    fn validate(&self, value: &str) -> Result<(), Error> {
+        let rules = &self.definition;
+        let invalid = |reason: String| {
+            Err(Error::ValidateInvalidParameter(
+                rules.name.clone(),
+                value.to_string(),
+                reason,
+            ))
+        };
+
+        let length = value.chars().count();
+        if let Some(min_length) = rules.min_length.filter(|min_length| length < *min_length) {
+            return invalid(format!("shorter than {min_length} characters"));
+        }
+        if let Some(max_length) = rules.max_length.filter(|max_length| length > *max_length) {
+            return invalid(format!("longer than {max_length} characters"));
+        }
+        if self
+            .pattern
+            .as_ref()
+            .is_some_and(|pattern| !pattern.is_match(value))
+        {
+            let pattern = rules.pattern.as_deref().unwrap_or_default();
+            return invalid(format!("doesn't match `{pattern}`"));
+        }
+        if !rules.one_of.is_empty() && !rules.one_of.iter().any(|allowed| allowed == value) {
+            return invalid(format!("expected one of `{}`", rules.one_of.join("`, `")));
+        }
+
+        let number = match rules.kind {
+            ParameterType::String => return Ok(()),
+            ParameterType::Integer => value.parse::<i64>().ok().map(|number| number as f64),
+            ParameterType::Number => value
+                .parse::<f64>()
+                .ok()
+                .filter(|number| number.is_finite()),
+            ParameterType::Timestamp => value.parse::<u64>().ok().map(|number| number as f64),
+        };
+        let Some(number) = number else {
+            return invalid(format!("expected {:?}", rules.kind).to_lowercase());
+        };
+        if let Some(min) = rules.min.filter(|min| number < *min) {
+            return invalid(format!("smaller than {min}"));
+        }
+        if let Some(max) = rules.max.filter(|max| number > *max) {
+            return invalid(format!("greater than {max}"));
+        }
+        Ok(())
+    }
+}
+
+/// Names and aliases of cli subcommands next to endpoints, including those of `diff` and
+/// the shell, and of the routes next to endpoints in `serve`
+pub const RESERVED_NAMES: [&str; 15] = [
+    "diff",
+    "d",
+    "ids",
+    "search",
+    "db",
+    "daemon",
+    "serve",
+    "shell",
+    "completions",
+    "manpage",
+    "tui",
+    "help",
+    "exit",
+    "quit",
+    "history",
+];
+
+/// Arguments the cli adds to endpoint subcommands, including the global ones
+pub const RESERVED_PARAMETERS: [&str; 8] = [
+    "json",
+    "form",
+    "watch",
+    "watch_diff",
+    "help",
+    "version",
+    "verbose",
+    "quiet",
+];
+
+/// Endpoint loaded from a definitions file
+#[derive(Debug)]
+pub struct DefinedEndpoint {
+    definition: EndpointDefinition,
+    method: Method,
+    parameters: Vec<DefinedParameter>,
+    pagination: Option<Pagination>,
+}
+impl DefinedEndpoint {
+    pub(crate) fn new(mut definition: EndpointDefinition) -> Result<Self, Error> {
+        let name = definition.name.clone();
+        let invalid = |reason: String| Error::EndpointInvalid(name.clone(), reason);
+
+        if definition.name.is_empty() {
+            return Err(invalid("name is empty".to_string()));
+        }
+        if let Some(name) = std::iter::once(&definition.name)
+            .chain(&definition.alias)
+            .find(|name| RESERVED_NAMES.contains(&name.as_str()))
+        {
+            return Err(invalid(format!(
+                "`{name}` is reserved for a cli subcommand or server route"
+            )));
+        }
+        let method = Method::from_bytes(definition.method.to_uppercase().as_bytes())
+            .map_err(|_| invalid(format!("unknown method `{}`", definition.method)))?;
+        for (header, value) in &definition.headers {
+            HeaderName::from_bytes(header.as_bytes())
+                .map_err(|_| invalid(format!("invalid header name `{header}`")))?;
+            HeaderValue::from_str(value)
+                .map_err(|_| invalid(format!("invalid value of header `{header}`")))?;
+        }
+
+        let mut parameters: Vec<DefinedParameter> = Vec::new();
+        let mut unfilled = definition.path.clone();
+        for parameter in std::mem::take(&mut definition.parameters) {
+            if RESERVED_PARAMETERS.contains(&parameter.name.as_str()) {
+                return Err(invalid(format!(
+                    "parameter `{}` is reserved for a cli argument",
+                    parameter.name
+                )));
+            }
+            if parameters
+                .iter()
+                .any(|defined| defined.definition.name == parameter.name)
+            {
+                return Err(invalid(format!("duplicate parameter `{}`", parameter.name)));
+            }
+            let placeholder = format!("{{{}}}", parameter.name);
+            if !definition.path.contains(&placeholder) {
+                return Err(invalid(format!("path lacks `{placeholder}`")));
+            }
+            unfilled = unfilled.replace(&placeholder, "");
+            let pattern = match &parameter.pattern {
+                Some(pattern) => {
+                    let regex = match parameter.search_pattern {
+                        true => pattern.clone(),
+                        false => format!("^(?:{pattern})$"),
+                    };
+                    Some(
+                        Regex::new(&regex)
+                            .map_err(|e| invalid(format!("invalid pattern `{pattern}`: {e}")))?,
+                    )
+                }
+                None => None,
+            };
+            parameters.push(DefinedParameter {
+                definition: parameter,
+                pattern,
+            });
+        }
+
+        // Placeholders without parameter would be sent literally
+        if let Some(start) = unfilled.find('{') {
+            let placeholder = unfilled[start..]
+                .split_inclusive('}')
+                .next()
+                .unwrap_or_default();
+            return Err(invalid(format!(
+                "path has `{placeholder}` without parameter"
+            )));
+        }
+
+        let pagination = definition.pagination.take().map(Pagination::from);
+        Ok(Self {
+            definition,
+            method,
+            parameters,
+            pagination,
+        })
+    }
+}
+impl Endpoint for DefinedEndpoint {
+    fn name(&self) -> &str {
+        &self.definition.name
+    }
+    fn stored_name(&self) -> &str {
+        self.definition
+            .stored_name
+            .as_deref()
+            .unwrap_or(&self.definition.name)
+    }
+    fn about(&self) -> &str {
+        &self.definition.about
+    }
+    fn alias(&self) -> Option<&str> {
+        self.definition.alias.as_deref()
+    }
+    fn method(&self) -> Method {
+        self.method.clone()
+    }
+    fn path(&self) -> &str {
+        &self.definition.path
+    }
+    fn parameters(&self) -> Vec<Parameter> {
+        self.parameters
+            .iter()
+            .map(|parameter| Parameter::new(&parameter.definition.name, &parameter.definition.help))
+            .collect()
+    }
+    fn validate(&self, values: &[String]) -> Result<(), Error> {
+        self.parameters
+            .iter()
+            .zip(values)
+            .try_for_each(|(parameter, value)| parameter.validate(value))
+    }
+    fn headers(&self) -> Vec<(String, String)> {
+        self.definition.headers.clone().into_iter().collect()
+    }
+    fn response_format(&self) -> ResponseFormat {
+        match self.definition.response_format {
+            FormatDefinition::Json => ResponseFormat::Json,
+            FormatDefinition::Text => ResponseFormat::Text,
+        }
+    }
+    fn response_schema(&self) -> Option<Value> {
+        self.definition.response_schema.clone()
+    }
+    fn pagination(&self) -> Option<Pagination> {
+        self.pagination.clone()
+    }
+}
+
+/// Parses endpoint definitions, YAML for `.yaml` and `.yml` files and TOML otherwise
+fn parse(path: &Path, raw: &str) -> Result<Definitions, Error> {
+    let parse_error = |e: String| Error::EndpointParse(path.into(), e);
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("yaml" | "yml") => serde_yaml::from_str(raw).map_err(|e| parse_error(e.to_string())),
+        _ => toml::from_str(raw).map_err(|e| parse_error(e.to_string())),
+    }
+}
+
+/// Loads endpoints from a definitions file, appended to `endpoints` whose names and
+/// aliases they must not reuse
+pub fn load(endpoints: &mut Vec<Arc<dyn Endpoint>>, path: &Path) -> Result<(), Error> {
+    let raw = std::fs::read_to_string(path).map_err(|e| Error::EndpointRead(path.into(), e))?;
+    for definition in parse(path, &raw)?.endpoints {
+        push(endpoints, DefinedEndpoint::new(definition)?)?;
+    }
+    Ok(())
+}
+
+/// Appends `defined` to `endpoints` unless its name or alias is already taken
+pub(crate) fn push(
+    endpoints: &mut Vec<Arc<dyn Endpoint>>,
+    defined: DefinedEndpoint,
+) -> Result<(), Error> {
+    let taken = |name: &str| {
+        endpoints
+            .iter()
+            .any(|endpoint| endpoint.name() == name || endpoint.alias() == Some(name))
+    };
+    if let Some(name) = std::iter::once(defined.name())
+        .chain(defined.alias())
+        .find(|name| taken(name))
+    {
+        return Err(Error::EndpointDuplicate(name.to_string()));
+    }
+    endpoints.push(Arc::new(defined));
+    Ok(())
+}
+
+#[test]
+fn load_test() {
+    use crate::endpoint::{builtin, find, Request};
+
+    let path = std::env::temp_dir().join("apicommand_definitions_test.yaml");
+    std::fs::write(
+        &path,
+        r#"
+endpoints:
+  - name: stock
+    about: stock levels of a product
+    method: post
+    path: stock/{sku}/{limit}
+    headers:
+      X-Tenant: acme
+    pagination:
+      strategy: cursor
+      pointer: /next
+      merge: true
+    parameters:
+      - name: sku
+        pattern: "[A-Z]{3}-\\d+"
+      - name: limit
+        type: integer
+        max: 100
+"#,
+    )
+    .unwrap();
+    let mut endpoints = builtin();
+    load(&mut endpoints, &path).unwrap();
+
+    let stock = find(&endpoints, "stock").unwrap();
+    assert_eq!(stock.method(), Method::POST);
+    assert_eq!(stock.stored_name(), "stock");
+    let pagination = Pagination::builder()
+        .strategy(PageStrategy::Cursor {
+            param: "cursor".to_string(),
+            pointer: "/next".to_string(),
+        })
+        .merge(true)
+        .build();
+    assert_eq!(stock.pagination(), Some(pagination));
+    assert_eq!(
+        stock.headers(),
+        vec![("X-Tenant".to_string(), "acme".to_string())]
+    );
+    let request = Request::new(stock.clone(), vec!["ABC-1".to_string(), "5".to_string()]);
+    assert_eq!(request.unwrap().path(), "stock/ABC-1/5");
+    for invalid in [["ABC-1x", "5"], ["ABC-1", "101"], ["ABC-1", "five"]] {
+        let values = invalid.map(String::from).to_vec();
+        assert!(Request::new(stock.clone(), values).is_err());
+    }
+
+    // Names of built-in endpoints can't be reused
+    assert!(matches!(
+        load(&mut endpoints, &path),
+        Err(Error::EndpointDuplicate(_))
+    ));
+    let toml_path = std::env::temp_dir().join("apicommand_definitions_test.toml");
+    std::fs::write(
+        &toml_path,
+        "[[endpoints]]\nname = \"broken\"\npath = \"broken\"\n\n[[endpoints.parameters]]\nname = \"id\"\n",
+    )
+    .unwrap();
+    assert!(matches!(
+        load(&mut builtin(), &toml_path),
+        Err(Error::EndpointInvalid(_, _))
+    ));
+
+    // Names of cli subcommands, shell commands, server routes and arguments, repeated
+    // parameters and placeholders without parameter are rejected
+    let names = ["serve", "ids", "history", "quit"]
+        .map(|name| format!("name = \"{name}\"\npath = \"thing\""));
+    let parameters = ["watch", "version", "verbose", "quiet"].map(|name| {
+        format!(
+            "name = \"thing\"\npath = \"thing/{{{name}}}\"\nparameters = [{{ name = \"{name}\" }}]"
+        )
+    });
+    for invalid in names.into_iter().chain(parameters).chain(
+        [
+            "name = \"thing\"\nalias = \"d\"\npath = \"thing\"",
+            "name = \"thing\"\npath = \"thing/{id}\"\nparameters = [{ name = \"id\" }, { name = \"id\" }]",
+            "name = \"thing\"\npath = \"thing/{id}/{other}\"\nparameters = [{ name = \"id\" }]",
+        ]
+        .map(String::from),
+    ) {
+        std::fs::write(&toml_path, format!("[[endpoints]]\n{invalid}\n")).unwrap();
+        assert!(matches!(
+            load(&mut builtin(), &toml_path),
+            Err(Error::EndpointInvalid(_, _))
+        ));
+    }
+}
@@ -0,0 +1,99 @@
+use crate::database::StoredResponse;
+
+use serde_json::Value;
+use std::fmt::Display;
+
+/// Structural difference at a JSON pointer path
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    Added(String, Value),
+    Removed(String, Value),
+    Changed(String, Value, Value),
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::Added(path, value) => write!(f, "+ `{path}`: {value}"),
+            Change::Removed(path, value) => write!(f, "- `{path}`: {value}"),
+            Change::Changed(path, old, new) => write!(f, "~ `{path}`: {old} -> {new}"),
+        }
+    }
+}
+
+/// Two stored responses and the changes from `old` to `new`
+#[derive(Debug)]
+pub struct ResponseDiff {
+    pub old: StoredResponse,
+    pub new: StoredResponse,
+    pub changes: Vec<Change>,
+}
+
+/// Compares two JSON values and lists added, removed and changed fields
+pub fn diff(old: &Value, new: &Value) -> Vec<Change> {
+    let mut changes = Vec::new();
+    compare(String::new(), old, new, &mut changes);
+    changes
+}
+
+fn compare(path: String, old: &Value, new: &Value, changes: &mut Vec<Change>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, old_value) in old {
+                let path = format!("{path}/{}", escape(key));
+                match new.get(key) {
+                    Some(new_value) => compare(path, old_value, new_value, changes),
+                    None => changes.push(Change::Removed(path, old_value.clone())),
+                }
+            }
+            for (key, new_value) in new {
+                if !old.contains_key(key) {
+                    let path = format!("{path}/{}", escape(key));
+                    changes.push(Change::Added(path, new_value.clone()));
+                }
+            }
+        }
+        // Arrays are compared by index
+        (Value::Array(old), Value::Array(new)) => {
+            for index in 0..old.len().max(new.len()) {
+                let path = format!("{path}/{index}");
+                match (old.get(index), new.get(index)) {
+                    (Some(old_value), Some(new_value)) => {
+                        compare(path, old_value, new_value, changes)
+                    }
+                    (Some(old_value), None) => {
+                        changes.push(Change::Removed(path, old_value.clone()))
+                    }
+                    (None, Some(new_value)) => changes.push(Change::Added(path, new_value.clone())),
+                    (None, None) => unreachable!("Index is below the length of one array"),
+                }
+            }
+        }
+        _ if old != new => changes.push(Change::Changed(path, old.clone(), new.clone())),
+        _ => {}
+    }
+}
+
+/// Escapes a key for use in a JSON pointer (RFC 6901)
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[test]
+fn diff_test() {
+    use serde_json::json;
+
+    let old = json!({ "name": "a", "tags": [1, 2], "gone": true, "a/b": 1 });
+    let new = json!({ "name": "b", "tags": [1], "added": null, "a/b": 1 });
+
+    assert_eq!(
+        diff(&old, &new),
+        vec![
+            Change::Removed("/gone".to_string(), json!(true)),
+            Change::Changed("/name".to_string(), json!("a"), json!("b")),
+            Change::Removed("/tags/1".to_string(), json!(2)),
+            Change::Added("/added".to_string(), json!(null)),
+        ]
+    );
+    assert!(diff(&old, &old).is_empty());
+}
@@ -0,0 +1,401 @@
+use crate::{
+    configuration::Pagination,
+    error::Error,
+    validate::{BrandId, DateTimeSpan, LocationId},
+};
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::Method;
+use serde_json::Value;
+use std::{fmt::Debug, sync::Arc};
+
+/// Characters escaped in parameter values, which fill a single path segment each
+const SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Named parameter of an endpoint, given as cli argument in the listed order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    pub help: String,
+}
+impl Parameter {
+    pub fn new(name: &str, help: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            help: help.to_string(),
+        }
+    }
+}
+
+/// Format of response bodies, sent as `Accept` header
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ResponseFormat {
+    /// Checked against the configured JSON Schema and compared field by field
+    #[default]
+    Json,
+    Text,
+}
+impl ResponseFormat {
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ResponseFormat::Json => "application/json",
+            ResponseFormat::Text => "text/plain",
+        }
+    }
+}
+
+/// Body sent with a request
+#[derive(Debug, Clone, PartialEq)]
+pub enum RequestBody {
+    Json(Value),
+    /// `application/x-www-form-urlencoded` fields
+    Form(Vec<(String, String)>),
+}
+impl RequestBody {
+    /// Body as sent, stored alongside the response
+    pub fn content(&self) -> String {
+        match self {
+            RequestBody::Json(value) => value.to_string(),
+            RequestBody::Form(fields) => url::form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(fields)
+                .finish(),
+        }
+    }
+}
+
+/// API endpoint, defined once to get library, storage and cli support
+pub trait Endpoint: Debug + Send + Sync {
+    /// Name used in configuration and as cli subcommand, e.g. `last_run`
+    fn name(&self) -> &str;
+    /// Name responses are stored with, e.g. `LastRun`
+    fn stored_name(&self) -> &str {
+        self.name()
+    }
+    /// Short description shown in cli help
+    fn about(&self) -> &str;
+    /// Short cli alias, e.g. `l`
+    fn alias(&self) -> Option<&str> {
+        None
+    }
+    fn method(&self) -> Method {
+        Method::GET
+    }
+    /// Whether requests may carry a [`RequestBody`], by default all but `GET` and `HEAD`
+    fn accepts_body(&self) -> bool {
+        !matches!(self.method(), Method::GET | Method::HEAD)
+    }
+    /// Path below the api root with `{parameter}` placeholders, e.g. `run/{brand_id}`
+    fn path(&self) -> &str;
+    fn parameters(&self) -> Vec<Parameter>;
+    /// Checks raw values given in the order of [`Endpoint::parameters`]
+    fn validate(&self, values: &[String]) -> Result<(), Error>;
+    /// Additional headers sent with every request
+    fn headers(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+    fn response_format(&self) -> ResponseFormat {
+        ResponseFormat::Json
+    }
+    /// JSON Schema of successful responses, used unless one is configured by name
+    fn response_schema(&self) -> Option<Value> {
+        None
+    }
+    /// How pages of list-style responses are followed, unless configured otherwise
+    fn pagination(&self) -> Option<Pagination> {
+        None
+    }
+}
+
+/// Request of an endpoint with validated parameter values
+#[derive(Debug, Clone)]
+pub struct Request {
+    endpoint: Arc<dyn Endpoint>,
+    values: Vec<String>,
+    body: Option<RequestBody>,
+}
+impl Request {
+    pub fn new(endpoint: Arc<dyn Endpoint>, raw_values: Vec<String>) -> Result<Self, Error> {
+        let expected = endpoint.parameters().len();
+        if raw_values.len() != expected {
+            return Err(Error::ValidateParameterCount(
+                endpoint.name().to_string(),
+                expected,
+                raw_values.len(),
+            ));
+        }
+        // Dot segments would be resolved, leaving the path of the endpoint
+        if let Some((parameter, value)) = endpoint
+            .parameters()
+            .iter()
+            .zip(&raw_values)
+            .find(|(_, value)| matches!(value.as_str(), "." | ".."))
+        {
+            return Err(Error::ValidateInvalidParameter(
+                parameter.name.clone(),
+                value.clone(),
+                "is a relative path segment".to_string(),
+            ));
+        }
+        endpoint.validate(&raw_values)?;
+        Ok(Self {
+            endpoint,
+            values: raw_values,
+            body: None,
+        })
+    }
+    /// Adds a body, if the endpoint accepts one
+    pub fn with_body(mut self, body: RequestBody) -> Result<Self, Error> {
+        if !self.endpoint.accepts_body() {
+            return Err(Error::ValidateUnexpectedBody(
+                self.endpoint.name().to_string(),
+            ));
+        }
+        self.body = Some(body);
+        Ok(self)
+    }
+    pub fn endpoint(&self) -> &dyn Endpoint {
+        self.endpoint.as_ref()
+    }
+    pub fn body(&self) -> Option<&RequestBody> {
+        self.body.as_ref()
+    }
+    /// Path with placeholders replaced by the percent-encoded parameter values
+    pub fn path(&self) -> String {
+        self.endpoint.parameters().iter().zip(&self.values).fold(
+            self.endpoint.path().to_string(),
+            |path, (parameter, value)| {
+                let value = utf8_percent_encode(value, SEGMENT).to_string();
+                path.replace(&format!("{{{}}}", parameter.name), &value)
+            },
+        )
+    }
+}
+
+/// Endpoints available without further configuration
+pub fn builtin() -> Vec<Arc<dyn Endpoint>> {
+    vec![
+        Arc::new(Get),
+        Arc::new(LastRun),
+        Arc::new(Run),
+        Arc::new(StartRun),
+        Arc::new(Specific),
+    ]
+}
+
+/// Endpoint with the given name among `endpoints`
+pub fn find(endpoints: &[Arc<dyn Endpoint>], name: &str) -> Result<Arc<dyn Endpoint>, Error> {
+    endpoints
+        .iter()
+        .find(|endpoint| endpoint.name() == name)
+        .cloned()
+        .ok_or(Error::ValidateUnknownEndpoint(name.to_string()))
+}
+
+#[derive(Debug)]
+pub struct Get;
+impl Endpoint for Get {
+    fn name(&self) -> &str {
+        "get"
+    }
+    fn stored_name(&self) -> &str {
+        "Get"
+    }
+    fn about(&self) -> &str {
+        "get API request"
+    }
+    fn alias(&self) -> Option<&str> {
+        Some("g")
+    }
+    fn path(&self) -> &str {
+        "get/{brand_id}"
+    }
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![Parameter::new("brand_id", "Valid brand id")]
+    }
+    fn validate(&self, values: &[String]) -> Result<(), Error> {
+        BrandId::new(self.name(), values[0].clone())?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct LastRun;
+impl Endpoint for LastRun {
+    fn name(&self) -> &str {
+        "last_run"
+    }
+    fn stored_name(&self) -> &str {
+        "LastRun"
+    }
+    fn about(&self) -> &str {
+        "last run API request"
+    }
+    fn alias(&self) -> Option<&str> {
+        Some("l")
+    }
+    fn path(&self) -> &str {
+        "last_run/{brand_id}/{location_id}"
+    }
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new("brand_id", "Valid brand id"),
+            Parameter::new("location_id", "Valid location id"),
+        ]
+    }
+    fn validate(&self, values: &[String]) -> Result<(), Error> {
+        BrandId::new(self.name(), values[0].clone())?;
+        LocationId::new(self.name(), values[1].clone())?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Run;
+impl Endpoint for Run {
+    fn name(&self) -> &str {
+        "run"
+    }
+    fn stored_name(&self) -> &str {
+        "Run"
+    }
+    fn about(&self) -> &str {
+        "run API request"
+    }
+    fn alias(&self) -> Option<&str> {
+        Some("r")
+    }
+    fn path(&self) -> &str {
+        "run/{brand_id}/{location_id}"
+    }
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new("brand_id", "Valid brand id"),
+            Parameter::new("location_id", "Valid location id"),
+        ]
+    }
+    fn validate(&self, values: &[String]) -> Result<(), Error> {
+        BrandId::new(self.name(), values[0].clone())?;
+        LocationId::new(self.name(), values[1].clone())?;
+        Ok(())
+    }
+}
+
+/// Triggers a run, taking its options as JSON body
+#[derive(Debug)]
+pub struct StartRun;
+impl Endpoint for StartRun {
+    fn name(&self) -> &str {
+        "start_run"
+    }
+    fn stored_name(&self) -> &str {
+        "StartRun"
+    }
+    fn about(&self) -> &str {
+        "start run API request, options are sent as JSON body"
+    }
+    fn method(&self) -> Method {
+        Method::POST
+    }
+    fn path(&self) -> &str {
+        "run/{brand_id}/{location_id}"
+    }
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new("brand_id", "Valid brand id"),
+            Parameter::new("location_id", "Valid location id"),
+        ]
+    }
+    fn validate(&self, values: &[String]) -> Result<(), Error> {
+        BrandId::new(self.name(), values[0].clone())?;
+        LocationId::new(self.name(), values[1].clone())?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Specific;
+impl Endpoint for Specific {
+    fn name(&self) -> &str {
+        "specific"
+    }
+    fn stored_name(&self) -> &str {
+        "Specific"
+    }
+    fn about(&self) -> &str {
+        "specific API request"
+    }
+    fn alias(&self) -> Option<&str> {
+        Some("s")
+    }
+    fn path(&self) -> &str {
+        "specific/{brand_id}/{location_id}/{from_date}/{to_date}"
+    }
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new("brand_id", "Valid brand id"),
+            Parameter::new("location_id", "Valid location id"),
+            Parameter::new("from_date", "Unix timestamp(ms)"),
+            Parameter::new("to_date", "Unix timestamp(ms) >= `from_date`"),
+        ]
+    }
+    fn validate(&self, values: &[String]) -> Result<(), Error> {
+        BrandId::new(self.name(), values[0].clone())?;
+        LocationId::new(self.name(), values[1].clone())?;
+        DateTimeSpan::new(self.name(), values[2].clone(), values[3].clone())?;
+        Ok(())
+    }
+}
+
+#[test]
+fn request_test() {
+    let request = Request::new(
+        Arc::new(Specific),
+        vec![
+            "b".to_string(),
+            "l".to_string(),
+            "1".to_string(),
+            "2".to_string(),
+        ],
+    )
+    .unwrap();
+    assert_eq!(request.path(), "specific/b/l/1/2");
+    assert_eq!(request.endpoint().stored_name(), "Specific");
+
+    let invalid_span = vec![
+        "b".to_string(),
+        "l".to_string(),
+        "2".to_string(),
+        "1".to_string(),
+    ];
+    assert!(Request::new(Arc::new(Specific), invalid_span).is_err());
+    assert!(Request::new(Arc::new(Get), vec![]).is_err());
+
+    let brand = vec!["b".to_string()];
+    let body = RequestBody::Form(vec![("a b".to_string(), "1&2".to_string())]);
+    assert_eq!(body.content(), "a+b=1%262");
+    assert!(Request::new(Arc::new(Get), brand)
+        .unwrap()
+        .with_body(body)
+        .is_err());
+    let values = vec!["b".to_string(), "l".to_string()];
+    let body = RequestBody::Json(serde_json::json!({ "force": true }));
+    let request = Request::new(Arc::new(StartRun), values).unwrap();
+    assert_eq!(request.with_body(body.clone()).unwrap().body(), Some(&body));
+    assert!(find(&builtin(), "unknown").is_err());
+
+    // Values stay within their path segment
+    let request = Request::new(
+        Arc::new(LastRun),
+        vec!["a/b".to_string(), "c?d#e".to_string()],
+    )
+    .unwrap();
+    assert_eq!(request.path(), "last_run/a%2Fb/c%3Fd%23e");
+    for value in ["..", "."] {
+        let values = vec![value.to_string(), "l".to_string()];
+        assert!(Request::new(Arc::new(LastRun), values).is_err());
+    }
+}
@@ -1,29 +1,294 @@
-use reqwest::header::InvalidHeaderValue;
+use reqwest::header::{InvalidHeaderName, InvalidHeaderValue};
+use std::path::PathBuf;
+use strum::{Display, IntoStaticStr};
 use thiserror::Error;
 
-#[derive(Error, Debug)]
+/// Variants carry the context of the failure, e.g. the operation and location of the
+/// database or the redacted url of a request, and keep the error causing them as source
+#[derive(Error, Debug, IntoStaticStr)]
 pub enum Error {
     // Database
-    #[error("Database error")]
-    DatabaseSqlx(#[from] sqlx::Error),
-    #[error("Database query error")]
-    DatabaseQuery(#[from] sea_query::error::Error),
+    /// Operation, e.g. `read response 5`, and location of the database
+    #[error("Database `{1}` failed to {0}")]
+    DatabaseSqlx(String, String, #[source] sqlx::Error),
+    /// Response id and location of the database
+    #[error("No stored response with id `{0}` in database `{1}`")]
+    DatabaseResponseNotFound(i64, String),
+    /// Stored endpoint name, url, number of responses found and location of the database
+    #[error(
+        "Expected at least two stored `{0}` responses for `{1}` in database `{3}`. But found `{2}`"
+    )]
+    DatabaseNotEnoughResponses(String, String, usize, String),
+    /// Response id and location of the database
+    #[error("Stored response `{0}` in database `{1}` doesn't match any endpoint")]
+    DatabaseUnknownRequest(i64, String),
+    /// Response id and location of the database
+    #[error(
+        "Stored request of response `{0}` in database `{1}` has redacted values and can't be re-issued"
+    )]
+    DatabaseRedactedRequest(i64, String),
+    #[error("Unable to access database file `{}`", .0.display())]
+    DatabaseFile(PathBuf, #[source] std::io::Error),
+    #[error("Unsupported database url `{0}`, expected `sqlite://`, `postgres://` or `ndjson://`")]
+    DatabaseUnsupportedUrl(String),
+    /// Storage backend, operation and location of the database
+    #[error("The {0} storage backend of `{2}` doesn't support {1}")]
+    DatabaseUnsupportedOperation(&'static str, &'static str, String),
+
+    // Endpoint
+    #[error("Unable to read endpoint definitions `{}`", .0.display())]
+    EndpointRead(PathBuf, #[source] std::io::Error),
+    #[error("Unable to parse endpoint definitions `{}`: {1}", .0.display())]
+    EndpointParse(PathBuf, String),
+    #[error("Invalid endpoint definition `{0}`: {1}")]
+    EndpointInvalid(String, String),
+    #[error("Endpoint name or alias `{0}` is already taken")]
+    EndpointDuplicate(String),
+
+    // Auth
+    #[error("Expected 200 OK status from token endpoint `{0}`. But received `{1}`")]
+    AuthTokenStatus(String, String),
+    #[error("Response of token endpoint `{0}` lacks a valid `{1}`")]
+    AuthInvalidTokenResponse(String, &'static str),
+
+    // Secret
+    #[error("Unable to read secret from `{0}`")]
+    SecretRead(String, #[source] std::io::Error),
+    #[error("Credential helper `{0}` failed with `{1}`")]
+    SecretHelperFailed(String, String),
+    #[error("Secret from `{0}` is empty")]
+    SecretEmpty(String),
 
     // Network
-    #[error("Request error")]
-    NetworkInvalidTimestamp(#[from] reqwest::Error),
-    #[error("Header error")]
-    NetworkInvalidHeaderValue(#[from] InvalidHeaderValue),
-    #[error("Expected 200 OK status from API. But received `{0}`")]
-    NetworkUnexpectedStatusCode(String),
+    /// Redacted url of the request, timeouts are told apart by [`reqwest::Error::is_timeout`]
+    #[error("Request to `{0}` failed")]
+    NetworkRequest(String, #[source] reqwest::Error),
+    #[error("Invalid value of header `{0}`")]
+    NetworkInvalidHeaderValue(String, #[source] InvalidHeaderValue),
+    #[error("Invalid header name `{0}`")]
+    NetworkInvalidHeaderName(String, #[source] InvalidHeaderName),
+    #[error("Invalid request url `{0}`")]
+    NetworkInvalidUrl(String, #[source] url::ParseError),
+    #[error("Page `{0}` is not valid JSON")]
+    NetworkInvalidPageJson(String, #[source] serde_json::Error),
+    /// Endpoint name, redacted url and status
+    #[error("Expected a successful 2xx status for `{0}` from `{1}`. But received `{2}`")]
+    NetworkUnexpectedStatusCode(String, String, String),
 
     // Validate
-    #[error("Invalid brand_id: `{0}`")]
-    ValidateInvalidBrandId(String),
-    #[error("Invalid location_id: `{0}`")]
-    ValidateInvalidLocationId(String),
-    #[error("Parse error for timestamp: `{0}`")]
-    ValidateInvalidTimestamp(#[from] std::num::ParseIntError),
-    #[error(r#"Invalid date_time_stamp: "to_date"=`{1}` can't be smaller than "from_date"=`{0}`"#)]
-    ValidateInvalidDateTimeSpan(u64, u64),
+    /// Endpoint name and value
+    #[error("Invalid brand_id for `{0}`: `{1}`")]
+    ValidateInvalidBrandId(String, String),
+    /// Endpoint name and value
+    #[error("Invalid location_id for `{0}`: `{1}`")]
+    ValidateInvalidLocationId(String, String),
+    /// Endpoint name and value
+    #[error("Parse error for timestamp of `{0}`: `{1}`")]
+    ValidateInvalidTimestamp(String, String, #[source] std::num::ParseIntError),
+    /// Endpoint name, from and to date
+    #[error(
+        r#"Invalid date_time_stamp of `{0}`: "to_date"=`{2}` can't be smaller than "from_date"=`{1}`"#
+    )]
+    ValidateInvalidDateTimeSpan(String, u64, u64),
+    #[error("Invalid response id: `{0}`")]
+    ValidateInvalidResponseId(String),
+    #[error("Endpoint `{0}` expects {1} parameters. But received {2}")]
+    ValidateParameterCount(String, usize, usize),
+    #[error("Invalid {0}: `{1}` {2}")]
+    ValidateInvalidParameter(String, String, String),
+    #[error("Endpoint `{0}` doesn't accept a request body")]
+    ValidateUnexpectedBody(String),
+    /// Query and reason given by the database
+    #[error("Invalid search query `{0}`: {1}")]
+    ValidateInvalidSearchQuery(String, String),
+    #[error("Unknown endpoint `{0}`")]
+    ValidateUnknownEndpoint(String),
+
+    // Schema
+    #[error("Unable to read schema file `{}`", .0.display())]
+    SchemaRead(PathBuf, #[source] std::io::Error),
+    #[error("Unable to parse schema file `{}`", .0.display())]
+    SchemaParse(PathBuf, #[source] serde_json::Error),
+    #[error("Invalid schema for `{0}`: {1}")]
+    SchemaInvalid(String, String),
+    #[error("Response for `{0}` violates its schema: {}", .1.join("; "))]
+    SchemaViolation(String, Vec<String>),
+
+    // Schedule
+    #[error("Unable to read schedule `{}`", .0.display())]
+    ScheduleRead(PathBuf, #[source] std::io::Error),
+    #[error("Unable to parse schedule `{}`: {1}", .0.display())]
+    ScheduleParse(PathBuf, String),
+    #[error("Invalid job `{0}`: {1}")]
+    ScheduleInvalidJob(String, String),
+
+    // Server
+    #[error("Unable to listen on `{0}`")]
+    ServerBind(String, #[source] std::io::Error),
+    #[error("Serving on `{0}` failed")]
+    ServerIo(String, #[source] std::io::Error),
+    #[error("Endpoint `{0}` expects {1} requests")]
+    ServerMethodNotAllowed(String, String),
+    #[error("Request body is not valid JSON")]
+    ServerInvalidJson(#[source] serde_json::Error),
+
+    // Diff
+    #[error("Stored response `{0}` is not valid JSON")]
+    DiffInvalidJson(i64, #[source] serde_json::Error),
+}
+
+/// Family of an error, each exiting the cli with its own documented code. `0` is success,
+/// `1` a failure outside of [`Error`], e.g. of the terminal, and `2` invalid cli usage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum ErrorFamily {
+    /// Invalid parameters, ids or request bodies
+    Validation = 3,
+    /// The API or token endpoint couldn't be reached or the server failed
+    Network = 4,
+    /// The API or token endpoint answered with an unexpected status
+    Status = 5,
+    /// A request took longer than `--timeout`
+    Timeout = 6,
+    /// Reading or writing stored responses failed
+    Database = 7,
+    /// A response body isn't what was expected, e.g. violates its schema
+    Response = 8,
+    /// Invalid endpoint definitions, schemas, schedules, secrets or listen address
+    Configuration = 9,
+}
+impl ErrorFamily {
+    pub fn exit_code(self) -> u8 {
+        self as u8
+    }
+}
+
+impl Error {
+    /// Name of the variant, e.g. `NetworkRequest`
+    pub fn kind(&self) -> &'static str {
+        self.into()
+    }
+
+    /// Message including those of its sources, e.g. for logs and HTTP responses
+    pub fn message(&self) -> String {
+        messages(self).join(": ")
+    }
+
+    pub fn family(&self) -> ErrorFamily {
+        match self {
+            Error::DatabaseSqlx(..)
+            | Error::DatabaseResponseNotFound(..)
+            | Error::DatabaseNotEnoughResponses(..)
+            | Error::DatabaseUnknownRequest(..)
+            | Error::DatabaseRedactedRequest(..)
+            | Error::DatabaseFile(..)
+            | Error::DatabaseUnsupportedUrl(_)
+            | Error::DatabaseUnsupportedOperation(..) => ErrorFamily::Database,
+            Error::EndpointRead(..)
+            | Error::EndpointParse(..)
+            | Error::EndpointInvalid(..)
+            | Error::EndpointDuplicate(_)
+            | Error::SecretRead(..)
+            | Error::SecretHelperFailed(..)
+            | Error::SecretEmpty(_)
+            | Error::SchemaRead(..)
+            | Error::SchemaParse(..)
+            | Error::SchemaInvalid(..)
+            | Error::ScheduleRead(..)
+            | Error::ScheduleParse(..)
+            | Error::ScheduleInvalidJob(..)
+            | Error::ServerBind(..) => ErrorFamily::Configuration,
+            Error::AuthTokenStatus(..) | Error::NetworkUnexpectedStatusCode(..) => {
+                ErrorFamily::Status
+            }
+            Error::NetworkRequest(_, e) if e.is_timeout() => ErrorFamily::Timeout,
+            Error::NetworkRequest(..)
+            | Error::NetworkInvalidHeaderValue(..)
+            | Error::NetworkInvalidHeaderName(..)
+            | Error::NetworkInvalidUrl(..)
+            | Error::ServerIo(..) => ErrorFamily::Network,
+            Error::AuthInvalidTokenResponse(..)
+            | Error::NetworkInvalidPageJson(..)
+            | Error::SchemaViolation(..)
+            | Error::DiffInvalidJson(..) => ErrorFamily::Response,
+            Error::ValidateInvalidBrandId(..)
+            | Error::ValidateInvalidLocationId(..)
+            | Error::ValidateInvalidTimestamp(..)
+            | Error::ValidateInvalidDateTimeSpan(..)
+            | Error::ValidateInvalidResponseId(_)
+            | Error::ValidateParameterCount(..)
+            | Error::ValidateInvalidParameter(..)
+            | Error::ValidateUnexpectedBody(_)
+            | Error::ValidateInvalidSearchQuery(..)
+            | Error::ValidateUnknownEndpoint(_)
+            | Error::ServerMethodNotAllowed(..)
+            | Error::ServerInvalidJson(_) => ErrorFamily::Validation,
+        }
+    }
+}
+
+/// Messages of an error and its chain of sources, leaving out sources whose message is
+/// already part of an earlier one
+pub fn messages(error: &(dyn std::error::Error + 'static)) -> Vec<String> {
+    let mut messages = vec![error.to_string()];
+    let mut source = error.source();
+    while let Some(error) = source {
+        let message = error.to_string();
+        if !messages.iter().any(|previous| previous.contains(&message)) {
+            messages.push(message);
+        }
+        source = error.source();
+    }
+    messages
+}
+
+#[test]
+fn family_test() {
+    assert_eq!(
+        Error::ValidateInvalidBrandId("get".to_string(), "brand".to_string()).family(),
+        ErrorFamily::Validation
+    );
+    assert_eq!(
+        Error::NetworkUnexpectedStatusCode(
+            "get".to_string(),
+            "http://localhost/get/brand".to_string(),
+            "500 Internal Server Error".to_string()
+        )
+        .family()
+        .exit_code(),
+        5
+    );
+    assert_eq!(
+        Error::DatabaseResponseNotFound(1, "test.sqlite3".to_string())
+            .family()
+            .exit_code(),
+        7
+    );
+}
+
+#[test]
+fn messages_test() {
+    let source = "x".parse::<u64>().unwrap_err();
+    let error = Error::ValidateInvalidTimestamp("specific".to_string(), "x".to_string(), source);
+    assert_eq!(error.kind(), "ValidateInvalidTimestamp");
+    assert_eq!(error.family().to_string(), "validation");
+    assert_eq!(
+        error.message(),
+        "Parse error for timestamp of `specific`: `x`: invalid digit found in string"
+    );
+
+    // Errors name the database and endpoint they occurred in
+    let error = Error::DatabaseResponseNotFound(5, "test.sqlite3".to_string());
+    assert_eq!(
+        error.message(),
+        "No stored response with id `5` in database `test.sqlite3`"
+    );
+    let error = Error::ValidateInvalidLocationId("run".to_string(), "x".to_string());
+    assert_eq!(error.message(), "Invalid location_id for `run`: `x`");
+    let error = crate::validate::DateTimeSpan::new("specific", "2".to_string(), "1".to_string())
+        .unwrap_err();
+    assert_eq!(
+        error.message(),
+        r#"Invalid date_time_stamp of `specific`: "to_date"=`1` can't be smaller than "from_date"=`2`"#
+    );
 }
@@ -1,24 +1,150 @@
 pub mod configuration;
+pub mod daemon;
+pub mod definition;
+pub mod diff;
+pub mod endpoint;
 pub mod error;
+pub mod openapi;
+pub mod secret;
+pub mod server;
+pub mod signing;
 pub mod validate;
 
 mod database;
 mod network;
+mod oauth;
+mod pagination;
+mod redact;
+mod schema;
+#[cfg(test)]
+mod test_support;
 
-use configuration::Config;
+use configuration::{Config, RetentionPolicy};
+use database::{PruneReport, SearchResult};
+pub use database::{SearchQuery, StoredResponse};
+use diff::ResponseDiff;
+use endpoint::{Endpoint, Request, RequestBody};
 use error::Error;
-use network::{ApiResponse, RequestType};
-use validate::{GetParameters, LastRunParameters, RunParameters, SpecificParameters};
+use network::{ApiResponse, Outcome};
+use percent_encoding::percent_decode_str;
+use validate::ResponseId;
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    future::Future,
+    sync::Arc,
+    time::Duration,
+};
+
+/// Sends the request, checks the response against its schema and saves it redacted into the
+/// database. Paginated requests save every page and return the first one, or all items
+/// merged if configured.
+/// Responses violating their schema are saved before the violation is returned as an error.
+async fn process(config: &Config, request: Request) -> Result<ApiResponse, Error> {
+    let pagination = config
+        .get_pagination()
+        .cloned()
+        .or_else(|| request.endpoint().pagination());
+    let mut pages = match &pagination {
+        Some(pagination) => network::send_pages(config, request, pagination).await?,
+        None => vec![network::send_api_request(config, request).await?],
+    };
+    let storage = database::open(config).await?;
+    for api_response in &mut pages {
+        schema::check(config, api_response)?;
+        redact::response(config, api_response);
+        storage.write(api_response).await?;
+    }
+    // Apply automatic retention policy
+    if let Some(policy) = config.get_retention() {
+        storage.prune(policy).await?;
+    }
+
+    for api_response in &pages {
+        if let Outcome::SchemaViolation(violations) = &api_response.outcome {
+            return Err(Error::SchemaViolation(
+                api_response.request.endpoint().name().to_string(),
+                violations.clone(),
+            ));
+        }
+    }
+    let merged = match &pagination {
+        Some(pagination) if pagination.get_merge() => Some(pagination::merge(pagination, &pages)?),
+        _ => None,
+    };
+    let mut api_response = pages.swap_remove(0);
+    if let Some(merged) = merged {
+        api_response.data = merged;
+    }
+    Ok(api_response)
+}
+
+/// request function returns the response of any endpoint providing its raw parameters and
+/// an optional body
+pub async fn request(
+    config: &Config,
+    endpoint: Arc<dyn Endpoint>,
+    raw_values: Vec<String>,
+    body: Option<RequestBody>,
+) -> Result<ApiResponse, Error> {
+    // Validate parameters
+    let request = Request::new(endpoint, raw_values)?;
+    let request = match body {
+        Some(body) => request.with_body(body)?,
+        None => request,
+    };
+    // Send API request, check and save result into database
+    process(config, request).await
+}
+
+/// watch function sends the request every `interval` until `stop` completes, storing every
+/// response. `changed` is called with the previous and the new response whenever the body
+/// differs from the previous one, and with the first response. Failed requests are logged
+/// and sent again on the next tick
+pub async fn watch<F>(
+    config: &Config,
+    endpoint: Arc<dyn Endpoint>,
+    raw_values: Vec<String>,
+    body: Option<RequestBody>,
+    interval: Duration,
+    stop: impl Future<Output = ()>,
+    mut changed: F,
+) -> Result<(), Error>
+where
+    F: FnMut(Option<&ApiResponse>, &ApiResponse),
+{
+    // Validate parameters once
+    let request = Request::new(endpoint, raw_values)?;
+    let request = match body {
+        Some(body) => request.with_body(body)?,
+        None => request,
+    };
+    let mut ticks = tokio::time::interval(interval);
+    // Slow responses delay the following requests instead of causing bursts
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    tokio::pin!(stop);
+
+    let mut previous: Option<ApiResponse> = None;
+    loop {
+        tokio::select! {
+            _ = &mut stop => return Ok(()),
+            _ = ticks.tick() => {}
+        }
+        match process(config, request.clone()).await {
+            Ok(api_response) => {
+                if previous.as_ref().map(|previous| &previous.data) != Some(&api_response.data) {
+                    changed(previous.as_ref(), &api_response);
+                    previous = Some(api_response);
+                }
+            }
+            Err(e) => tracing::warn!("Watched request failed: {}", e.message()),
+        }
+    }
+}
 
 /// get function returns X from API providing brand_id
 pub async fn get(config: &Config, raw_brand_id: String) -> Result<ApiResponse, Error> {
-    // Validate parameters
-    let parameters = GetParameters::new(raw_brand_id)?;
-    // Send API requests
-    let api_response = network::send_api_request(config, RequestType::Get(parameters)).await?;
-    // Save result into database
-    database::write(config, &api_response).await?;
-    Ok(api_response)
+    request(config, Arc::new(endpoint::Get), vec![raw_brand_id], None).await
 }
 
 /// last_run function returns X from API providing brand_id and location_id
@@ -27,13 +153,8 @@ pub async fn last_run(
     raw_brand_id: String,
     raw_location_id: String,
 ) -> Result<ApiResponse, Error> {
-    // Validate parameters
-    let parameters = LastRunParameters::new(raw_brand_id, raw_location_id)?;
-    // Send API requests
-    let api_response = network::send_api_request(config, RequestType::LastRun(parameters)).await?;
-    // Save result into database
-    database::write(config, &api_response).await?;
-    Ok(api_response)
+    let raw_values = vec![raw_brand_id, raw_location_id];
+    request(config, Arc::new(endpoint::LastRun), raw_values, None).await
 }
 
 /// run function returns X from API providing brand_id and location_id
@@ -42,13 +163,20 @@ pub async fn run(
     raw_brand_id: String,
     raw_location_id: String,
 ) -> Result<ApiResponse, Error> {
-    // Validate parameters
-    let parameters = RunParameters::new(raw_brand_id, raw_location_id)?;
-    // Send API requests
-    let api_response = network::send_api_request(config, RequestType::Run(parameters)).await?;
-    // Save result into database
-    database::write(config, &api_response).await?;
-    Ok(api_response)
+    let raw_values = vec![raw_brand_id, raw_location_id];
+    request(config, Arc::new(endpoint::Run), raw_values, None).await
+}
+
+/// start_run function triggers a run providing brand_id, location_id and JSON options
+pub async fn start_run(
+    config: &Config,
+    raw_brand_id: String,
+    raw_location_id: String,
+    options: serde_json::Value,
+) -> Result<ApiResponse, Error> {
+    let raw_values = vec![raw_brand_id, raw_location_id];
+    let body = Some(RequestBody::Json(options));
+    request(config, Arc::new(endpoint::StartRun), raw_values, body).await
 }
 
 /// specific function returns X from API providing brand_id, location_id and to/from dates
@@ -59,14 +187,218 @@ pub async fn specific(
     raw_from_date: String,
     raw_to_date: String,
 ) -> Result<ApiResponse, Error> {
+    let raw_values = vec![raw_brand_id, raw_location_id, raw_from_date, raw_to_date];
+    request(config, Arc::new(endpoint::Specific), raw_values, None).await
+}
+
+fn compare(old: StoredResponse, new: StoredResponse) -> Result<ResponseDiff, Error> {
+    let parse = |response: &StoredResponse| {
+        serde_json::from_str(&response.data).map_err(|e| Error::DiffInvalidJson(response.id, e))
+    };
+    let changes = diff::diff(&parse(&old)?, &parse(&new)?);
+    Ok(ResponseDiff { old, new, changes })
+}
+
+/// diff function compares two stored responses providing their ids
+pub async fn diff(
+    config: &Config,
+    raw_old_id: String,
+    raw_new_id: String,
+) -> Result<ResponseDiff, Error> {
     // Validate parameters
-    let parameters =
-        SpecificParameters::new(raw_brand_id, raw_location_id, raw_from_date, raw_to_date)?;
-    // Send API requests
-    let api_response = network::send_api_request(config, RequestType::Specific(parameters)).await?;
-    // Save result into database
-    database::write(config, &api_response).await?;
-    Ok(api_response)
+    let old_id = ResponseId::new(raw_old_id)?;
+    let new_id = ResponseId::new(raw_new_id)?;
+    // Read responses from database
+    let storage = database::open(config).await?;
+    let old = storage.read(old_id.get()).await?;
+    let new = storage.read(new_id.get()).await?;
+    compare(old, new)
+}
+
+/// diff_latest function compares the two most recent stored responses of a request
+pub async fn diff_latest(config: &Config, request: Request) -> Result<ResponseDiff, Error> {
+    let url = network::stored_url(config, &request);
+    let stored_name = request.endpoint().stored_name();
+    // Read responses from database, newest first
+    let storage = database::open(config).await?;
+    let responses = storage.latest(stored_name, &url, 2).await?;
+    let found = responses.len();
+    let mut responses = responses.into_iter();
+    match (responses.next(), responses.next()) {
+        (Some(new), Some(old)) => compare(old, new),
+        _ => Err(Error::DatabaseNotEnoughResponses(
+            stored_name.to_string(),
+            url,
+            found,
+            storage.location(),
+        )),
+    }
+}
+
+/// diff_previous function compares a stored response with the preceding one of the same
+/// request
+pub async fn diff_previous(config: &Config, stored: StoredResponse) -> Result<ResponseDiff, Error> {
+    let storage = database::open(config).await?;
+    match storage.previous(&stored).await? {
+        Some(old) => compare(old, stored),
+        None => Err(Error::DatabaseNotEnoughResponses(
+            stored.request_type,
+            stored.url,
+            1,
+            storage.location(),
+        )),
+    }
+}
+
+/// stored function returns a stored response providing its id
+pub async fn stored(config: &Config, raw_id: String) -> Result<StoredResponse, Error> {
+    // Validate parameters
+    let id = ResponseId::new(raw_id)?;
+    database::open(config).await?.read(id.get()).await
+}
+
+/// history function returns the latest stored responses, optionally of one stored endpoint
+/// name
+pub async fn history(
+    config: &Config,
+    request_type: Option<&str>,
+    limit: u64,
+) -> Result<Vec<StoredResponse>, Error> {
+    database::open(config)
+        .await?
+        .history(request_type, limit)
+        .await
+}
+
+/// Parameter values of a stored url of the endpoint, matching the segments of its path
+/// template from the end as the api root may have a path itself
+fn stored_values(endpoint: &dyn Endpoint, url: &str) -> Vec<(String, String)> {
+    let Some(segments) = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| Some(url.path_segments()?.map(str::to_string).collect::<Vec<_>>()))
+    else {
+        return Vec::new();
+    };
+    let template: Vec<&str> = endpoint.path().split('/').collect();
+    let Some(segments) = segments.get(segments.len().saturating_sub(template.len())..) else {
+        return Vec::new();
+    };
+    if segments.len() != template.len() {
+        return Vec::new();
+    }
+
+    let mut values = Vec::new();
+    for (part, segment) in template.iter().zip(segments) {
+        match part
+            .strip_prefix('{')
+            .and_then(|part| part.strip_suffix('}'))
+        {
+            Some(name) => {
+                let value = percent_decode_str(segment).decode_utf8_lossy();
+                values.push((name.to_string(), value.into_owned()));
+            }
+            None if part == segment => {}
+            None => return Vec::new(),
+        }
+    }
+    values
+}
+
+/// Request a stored response answered, found among the endpoints stored under its request
+/// type by matching their path templates against its url. `None` if no endpoint matches
+fn stored_request(
+    endpoints: &[Arc<dyn Endpoint>],
+    stored: &StoredResponse,
+) -> Result<Option<Request>, Error> {
+    // Bodies are stored as sent, either JSON or form encoded
+    let body = stored.request_body.as_deref().map(|content| {
+        serde_json::from_str(content)
+            .map(RequestBody::Json)
+            .unwrap_or_else(|_| {
+                RequestBody::Form(
+                    url::form_urlencoded::parse(content.as_bytes())
+                        .into_owned()
+                        .collect(),
+                )
+            })
+    });
+    for endpoint in endpoints
+        .iter()
+        .filter(|endpoint| endpoint.stored_name() == stored.request_type)
+    {
+        let values = stored_values(endpoint.as_ref(), &stored.url);
+        let Some(raw_values) = endpoint
+            .parameters()
+            .iter()
+            .map(|parameter| {
+                values
+                    .iter()
+                    .find(|(name, _)| *name == parameter.name)
+                    .map(|(_, value)| value.clone())
+            })
+            .collect::<Option<Vec<_>>>()
+        else {
+            continue;
+        };
+        let request = Request::new(endpoint.clone(), raw_values)?;
+        return match body {
+            Some(body) => request.with_body(body).map(Some),
+            None => Ok(Some(request)),
+        };
+    }
+    Ok(None)
+}
+
+/// reissue function sends the request of a stored response again, with the same path
+/// parameters and body. Requests whose body or query had values redacted are refused, as
+/// only the redacted copies are stored
+pub async fn reissue(
+    config: &Config,
+    endpoints: &[Arc<dyn Endpoint>],
+    stored: &StoredResponse,
+) -> Result<ApiResponse, Error> {
+    let Some(request) = stored_request(endpoints, stored)? else {
+        let location = database::open(config).await?.location();
+        return Err(Error::DatabaseUnknownRequest(stored.id, location));
+    };
+    // Redacted values would be sent literally
+    let redacted = reqwest::Url::parse(&stored.url)
+        .is_ok_and(|url| redact::is_redacted(config, &url, request.body()));
+    if redacted {
+        let location = database::open(config).await?.location();
+        return Err(Error::DatabaseRedactedRequest(stored.id, location));
+    }
+    process(config, request).await
+}
+
+/// known_values function returns the values of endpoint parameters found in stored
+/// requests, keyed by parameter name, e.g. the brand ids for `brand_id`
+pub async fn known_values(
+    config: &Config,
+    endpoints: &[Arc<dyn Endpoint>],
+) -> Result<BTreeMap<String, BTreeSet<String>>, Error> {
+    let mut known: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for (request_type, url) in database::open(config).await?.requests().await? {
+        for endpoint in endpoints
+            .iter()
+            .filter(|endpoint| endpoint.stored_name() == request_type)
+        {
+            for (name, value) in stored_values(endpoint.as_ref(), &url) {
+                known.entry(name).or_default().insert(value);
+            }
+        }
+    }
+    Ok(known)
+}
+
+/// prune function deletes stored responses not kept by the retention policy
+pub async fn prune(config: &Config, policy: &RetentionPolicy) -> Result<PruneReport, Error> {
+    database::open(config).await?.prune(policy).await
+}
+
+/// search function returns stored responses whose body matches a full-text query
+pub async fn search(config: &Config, query: &SearchQuery) -> Result<Vec<SearchResult>, Error> {
+    database::open(config).await?.search(query).await
 }
 
 #[tokio::test]
@@ -175,3 +507,173 @@ async fn specific_test() {
     );
     println!("{}", response.url.as_str());
 }
+
+#[test]
+fn stored_values_test() {
+    let values = stored_values(
+        &endpoint::LastRun,
+        "http://localhost/api/last_run/brand/location?api_key=REDACTED",
+    );
+    assert_eq!(
+        values,
+        [
+            ("brand_id".to_string(), "brand".to_string()),
+            ("location_id".to_string(), "location".to_string()),
+        ]
+    );
+    assert!(stored_values(&endpoint::LastRun, "http://localhost/run/brand/location").is_empty());
+}
+
+#[tokio::test]
+async fn diff_latest_test() {
+    use crate::test_support::stub_server;
+
+    let (root, _) = stub_server(|_, count| (200, format!(r#"{{"step": {count}}}"#))).await;
+    let path = std::env::temp_dir().join("apicommand_diff_latest_test.sqlite3");
+    let _ = std::fs::remove_file(&path);
+    // Stored urls are normalized, as is the url of the response
+    let config = Config::builder()
+        .api_root(format!("{}/./api", root.replace("http://", "HTTP://")))
+        .api_key(None::<String>)
+        .db_path(path)
+        .build();
+    let endpoint: Arc<dyn Endpoint> = Arc::new(endpoint::Get);
+    for _ in 0..2 {
+        request(&config, endpoint.clone(), vec!["a b".to_string()], None)
+            .await
+            .unwrap();
+    }
+    let request = Request::new(endpoint, vec!["a b".to_string()]).unwrap();
+    let response_diff = diff_latest(&config, request).await.unwrap();
+    assert_eq!(response_diff.new.url, format!("{root}/api/get/a%20b"));
+}
+
+#[tokio::test]
+async fn watch_test() {
+    use crate::test_support::stub_server;
+
+    // Bodies change with every second request
+    let (api_root, _) =
+        stub_server(|_, count| (200, format!(r#"{{"step": {}}}"#, count / 2))).await;
+    let config = Config::builder()
+        .api_root(api_root)
+        .api_key(None::<String>)
+        .db_path(std::path::PathBuf::from("test.sqlite3"))
+        .no_store(true)
+        .build();
+    let mut changes = Vec::new();
+    watch(
+        &config,
+        Arc::new(endpoint::Get),
+        vec!["brand".to_string()],
+        None,
+        Duration::from_millis(100),
+        tokio::time::sleep(Duration::from_millis(550)),
+        |previous, new| changes.push((previous.map(|p| p.data.clone()), new.data.clone())),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        changes,
+        [
+            (None, r#"{"step": 0}"#.to_string()),
+            (
+                Some(r#"{"step": 0}"#.to_string()),
+                r#"{"step": 1}"#.to_string()
+            ),
+            (
+                Some(r#"{"step": 1}"#.to_string()),
+                r#"{"step": 2}"#.to_string()
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn reissue_test() {
+    use crate::{configuration::Redaction, test_support::stub_server};
+
+    let (api_root, _) = stub_server(|_, count| (200, format!(r#"{{"step": {count}}}"#))).await;
+    let path = std::env::temp_dir().join("apicommand_reissue_test.sqlite3");
+    let _ = std::fs::remove_file(&path);
+    let config = Config::builder()
+        .api_root(api_root)
+        .api_key(None::<String>)
+        .db_path(path)
+        .build();
+    let endpoints = endpoint::builtin();
+    let body = RequestBody::Json(serde_json::json!({ "dry_run": true }));
+    request(
+        &config,
+        Arc::new(endpoint::StartRun),
+        vec!["brand".to_string(), "location".to_string()],
+        Some(body.clone()),
+    )
+    .await
+    .unwrap();
+
+    let first = history(&config, None, 1).await.unwrap().remove(0);
+    assert!(diff_previous(&config, first.clone()).await.is_err());
+    let api_response = reissue(&config, &endpoints, &first).await.unwrap();
+    assert_eq!(api_response.request.body(), Some(&body));
+
+    let second = history(&config, None, 1).await.unwrap().remove(0);
+    assert_eq!(second.request_body, first.request_body);
+    let response_diff = diff_previous(&config, second).await.unwrap();
+    assert_eq!(response_diff.old.id, first.id);
+    assert_eq!(
+        response_diff.changes,
+        [diff::Change::Changed(
+            "/step".to_string(),
+            serde_json::json!(0),
+            serde_json::json!(1)
+        )]
+    );
+
+    let unknown = StoredResponse {
+        request_type: "Run".to_string(),
+        url: "http://localhost/elsewhere".to_string(),
+        ..first.clone()
+    };
+    assert!(matches!(
+        reissue(&config, &endpoints, &unknown).await,
+        Err(Error::DatabaseUnknownRequest(..))
+    ));
+
+    // Values replaced by the redaction rules would be sent literally, others are sent as
+    // stored even if they look redacted
+    let redacting = Config::builder()
+        .api_root(config.get_api_root().to_string())
+        .api_key(None::<String>)
+        .db_path(config.get_db_path().to_path_buf())
+        .redaction(
+            Redaction::builder()
+                .query_params(vec!["token".to_string()])
+                .json_pointers(vec!["/password".to_string()])
+                .build(),
+        )
+        .build();
+    let redacted = StoredResponse {
+        request_body: Some(r#"{"password":"REDACTED"}"#.to_string()),
+        ..first.clone()
+    };
+    assert!(matches!(
+        reissue(&redacting, &endpoints, &redacted).await,
+        Err(Error::DatabaseRedactedRequest(..))
+    ));
+    assert!(reissue(&config, &endpoints, &redacted).await.is_ok());
+    let literal = StoredResponse {
+        request_body: Some(r#"{"note":"REDACTED"}"#.to_string()),
+        ..first.clone()
+    };
+    assert!(reissue(&redacting, &endpoints, &literal).await.is_ok());
+    let redacted = StoredResponse {
+        url: format!("{}?token=REDACTED", first.url),
+        ..first
+    };
+    assert!(matches!(
+        reissue(&redacting, &endpoints, &redacted).await,
+        Err(Error::DatabaseRedactedRequest(..))
+    ));
+}
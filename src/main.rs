@@ -1,16 +1,571 @@
-use apicommand::{configuration::Config, get, last_run, run, specific};
+mod shell;
+mod tui;
+
+use apicommand::{
+    configuration::{
+        AuthScheme, ClientCredentials, Config, PageStrategy, Pagination, Redaction, RetentionPolicy,
+    },
+    daemon, definition, diff,
+    diff::ResponseDiff,
+    diff_latest,
+    endpoint::{self, Endpoint, Request, RequestBody},
+    error::{self, Error},
+    known_values, openapi, prune, request, search,
+    secret::Secret,
+    server,
+    signing::{HmacSigner, RequestSigner, SignatureEncoding},
+    watch, SearchQuery,
+};
 use tracing::Level;
 
-use std::path::PathBuf;
+use chrono::{DateTime, Utc};
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    io::{IsTerminal, Write},
+    path::PathBuf,
+    process::ExitCode,
+    sync::Arc,
+    time::Duration,
+};
 
-use clap::{arg, command, Arg, ArgAction, Command};
-use color_eyre::eyre::Result;
+use clap::{arg, command, Arg, ArgAction, ArgGroup, ArgMatches, Command};
+use clap_complete::{env::Shells, ArgValueCandidates, CompleteEnv, CompletionCandidate};
+use color_eyre::eyre::{Report, Result};
+use serde_json::json;
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    color_eyre::install()?;
+/// Parses `request_type=path` pairs for `--schema`
+fn parse_schema(raw: &str) -> Result<(String, PathBuf), String> {
+    match raw.split_once('=') {
+        Some((request_type, path)) if !request_type.is_empty() && !path.is_empty() => {
+            Ok((request_type.to_string(), PathBuf::from(path)))
+        }
+        _ => Err(format!("expected `request_type=path`, got `{raw}`")),
+    }
+}
+
+/// Parses a JSON request body for `--json`, `@path` reads it from a file
+fn parse_json(raw: &str) -> Result<serde_json::Value, String> {
+    let json = match raw.strip_prefix('@') {
+        Some(path) => std::fs::read_to_string(path).map_err(|e| format!("`{path}`: {e}"))?,
+        None => raw.to_string(),
+    };
+    serde_json::from_str(&json).map_err(|e| format!("invalid JSON: {e}"))
+}
+
+/// Parses `name=value` pairs for `--form`
+fn parse_form(raw: &str) -> Result<(String, String), String> {
+    match raw.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected `name=value`, got `{raw}`")),
+    }
+}
+
+/// Parses an interval for `--watch` in seconds, or with a `s`, `m` or `h` suffix
+fn parse_interval(raw: &str) -> Result<Duration, String> {
+    let (number, unit) = match raw.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => raw.split_at(index),
+        None => (raw, "s"),
+    };
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => {
+            return Err(format!(
+                "expected seconds, `30s`, `5m` or `1h`, got `{raw}`"
+            ))
+        }
+    };
+    match number.parse::<u64>() {
+        Ok(number) if number > 0 => number
+            .checked_mul(seconds)
+            .map(Duration::from_secs)
+            .ok_or(format!("interval `{raw}` is too long")),
+        _ => Err(format!("expected a positive interval, got `{raw}`")),
+    }
+}
+
+/// File given to `--{long}` on the command line. Read before parsing the command line, as
+/// endpoint files add subcommands
+fn early_file(long: &str) -> Option<PathBuf> {
+    early_arg(long).map(PathBuf::from)
+}
+
+/// Value given to `--{long}` on the command line, found without parsing it
+fn early_arg(long: &str) -> Option<OsString> {
+    let flag = format!("--{long}");
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag.as_str() {
+            return args.next();
+        }
+        if let Some(value) = arg
+            .to_str()
+            .and_then(|arg| arg.strip_prefix(&flag))
+            .and_then(|rest| rest.strip_prefix('='))
+        {
+            return Some(value.into());
+        }
+    }
+    None
+}
+
+/// Completes a parameter with the values stored for it, read from the database given on the
+/// command line being completed
+fn stored_candidates(endpoints: &[Arc<dyn Endpoint>], name: &str) -> ArgValueCandidates {
+    let endpoints = endpoints.to_vec();
+    let name = name.to_string();
+    ArgValueCandidates::new(move || {
+        let db_url = early_file("database_url").map(|url| url.to_string_lossy().into_owned());
+        let db_path = early_file("database_path").unwrap_or(PathBuf::from("test.sqlite3"));
+        // Completing must not create a database
+        if db_url.is_none() && !db_path.exists() {
+            return Vec::new();
+        }
+        let config = Config::builder()
+            .api_root(String::new())
+            .api_key(None::<Secret>)
+            .db_path(db_path)
+            .db_url(db_url)
+            .build();
+        // Completion runs at the start of `run`, within its multi-threaded runtime
+        let known = tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(known_values(&config, &endpoints))
+        });
+        known
+            .ok()
+            .and_then(|mut known| known.remove(&name))
+            .into_iter()
+            .flatten()
+            .map(CompletionCandidate::new)
+            .collect()
+    })
+}
+
+/// Writes the script completing in `shell`, calling back into this binary for candidates like
+/// `CompleteEnv` does
+fn write_completions(shell: &str, buf: &mut dyn Write) -> Result<()> {
+    let shells = Shells::builtins();
+    let shell = shells
+        .completer(shell)
+        .expect("Possible values are set in clap");
+    let mut completer = std::env::args_os()
+        .next()
+        .map(PathBuf::from)
+        .unwrap_or(PathBuf::from("apicommand"));
+    // Relative paths only work from the current directory
+    if completer.components().count() > 1 {
+        completer = std::env::current_dir()?.join(completer);
+    }
+    shell.write_registration(
+        "COMPLETE",
+        "apicommand",
+        "apicommand",
+        &completer.to_string_lossy(),
+        buf,
+    )?;
+    Ok(())
+}
+
+/// Subcommands sending API requests, also used to select stored responses
+fn request_subcommands(endpoints: &[Arc<dyn Endpoint>]) -> Vec<Command> {
+    endpoints
+        .iter()
+        .map(|endpoint| {
+            let command = Command::new(endpoint.name().to_string())
+                .about(endpoint.about().to_string())
+                .args(endpoint.parameters().into_iter().map(|parameter| {
+                    let candidates = stored_candidates(endpoints, &parameter.name);
+                    Arg::new(parameter.name)
+                        .required(true)
+                        .help(parameter.help)
+                        .add(candidates)
+                }));
+            match endpoint.alias() {
+                Some(alias) => command.visible_alias(alias.to_string()),
+                None => command,
+            }
+        })
+        .collect()
+}
+
+/// Adds the request body arguments if the endpoint accepts a body
+fn body_args(command: Command, endpoint: &dyn Endpoint) -> Command {
+    if !endpoint.accepts_body() {
+        return command;
+    }
+    command
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .value_parser(parse_json)
+                .help("JSON request body, `@path` reads it from a file"),
+        )
+        .arg(
+            Arg::new("form")
+                .long("form")
+                .action(ArgAction::Append)
+                .value_parser(parse_form)
+                .conflicts_with("json")
+                .help("Field of a form request body, e.g. `name=value`"),
+        )
+}
+
+/// Adds the arguments repeating a request periodically, unless the endpoint accepts a body
+/// as its requests usually trigger work
+fn watch_args(command: Command, endpoint: &dyn Endpoint) -> Command {
+    if endpoint.accepts_body() {
+        return command;
+    }
+    command
+        .arg(
+            Arg::new("watch")
+                .long("watch")
+                .value_name("interval")
+                .value_parser(parse_interval)
+                .help("Send the request every interval, e.g. `30s` or `5m`, printing the body whenever it changes"),
+        )
+        .arg(
+            Arg::new("watch_diff")
+                .long("watch_diff")
+                .action(ArgAction::SetTrue)
+                .requires("watch")
+                .help("Print the changed JSON fields instead of the whole body"),
+        )
+}
+
+/// Subcommands sending requests and reading stored responses, also available in the shell
+fn subcommands(endpoints: &[Arc<dyn Endpoint>]) -> Vec<Command> {
+    request_subcommands(endpoints)
+        .into_iter()
+        .zip(endpoints)
+        .map(|(command, endpoint)| {
+            watch_args(body_args(command, endpoint.as_ref()), endpoint.as_ref())
+        })
+        .chain([
+            Command::new("diff")
+                .visible_alias("d")
+                .about("compare stored responses")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("ids")
+                        .about("compare two stored responses by id")
+                        .arg(arg!(<old_id> "Id of the older response"))
+                        .arg(arg!(<new_id> "Id of the newer response")),
+                )
+                .subcommands(
+                    request_subcommands(endpoints)
+                        .into_iter()
+                        .map(|c| c.about("compare the latest two stored responses of a request")),
+                ),
+            Command::new("search")
+                .about("full-text search over stored responses")
+                .arg(arg!(<query> "FTS5 query, e.g. `X123` or `\"store code\" AND X*`"))
+                .arg(
+                    Arg::new("request_type")
+                        .long("request_type")
+                        .help("Only responses of this request, e.g. `get`"),
+                )
+                .arg(
+                    Arg::new("since")
+                        .long("since")
+                        .value_parser(str::parse::<DateTime<Utc>>)
+                        .help("Only responses stored at or after this RFC 3339 date time"),
+                )
+                .arg(
+                    Arg::new("until")
+                        .long("until")
+                        .value_parser(str::parse::<DateTime<Utc>>)
+                        .help("Only responses stored at or before this RFC 3339 date time"),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .long("limit")
+                        .default_value("20")
+                        .value_parser(clap::value_parser!(u64))
+                        .help("Maximum number of results"),
+                ),
+            Command::new("db")
+                .about("manage the response database")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("prune")
+                        .about("delete stored responses, keeping those matching any rule")
+                        .arg(
+                            Arg::new("keep_days")
+                                .long("keep_days")
+                                .value_parser(clap::value_parser!(u32))
+                                .help("Keep responses younger than this many days"),
+                        )
+                        .arg(
+                            Arg::new("keep_last")
+                                .long("keep_last")
+                                .value_parser(clap::value_parser!(u32))
+                                .help("Keep this many latest responses per request"),
+                        )
+                        .arg(
+                            Arg::new("prune_failures")
+                                .long("prune_failures")
+                                .action(ArgAction::SetTrue)
+                                .help("Also prune responses whose outcome isn't `Success`"),
+                        )
+                        .arg(
+                            Arg::new("vacuum")
+                                .long("vacuum")
+                                .action(ArgAction::SetTrue)
+                                .help("Reclaim free space after pruning"),
+                        ),
+                ),
+        ])
+        .collect()
+}
+
+/// Request body given to a subcommand, if any
+fn body(sub_matches: &ArgMatches) -> Option<RequestBody> {
+    // Subcommands of endpoints without a body lack the arguments
+    if let Ok(Some(json)) = sub_matches.try_get_one::<serde_json::Value>("json") {
+        return Some(RequestBody::Json(json.clone()));
+    }
+    match sub_matches.try_get_many::<(String, String)>("form") {
+        Ok(Some(fields)) => Some(RequestBody::Form(fields.cloned().collect())),
+        _ => None,
+    }
+}
+
+/// Raw value of a required subcommand argument
+fn raw_arg(sub_matches: &ArgMatches, id: &str) -> String {
+    sub_matches
+        .get_one::<String>(id)
+        .expect("String parses any input")
+        .to_owned()
+}
+
+/// Raw subcommand arguments in the order of the endpoint parameters
+fn raw_values(endpoint: &dyn Endpoint, sub_matches: &ArgMatches) -> Vec<String> {
+    endpoint
+        .parameters()
+        .iter()
+        .map(|parameter| raw_arg(sub_matches, &parameter.name))
+        .collect()
+}
+
+fn print_diff(response_diff: &ResponseDiff) {
+    let ResponseDiff { old, new, changes } = response_diff;
+    println!("--- #{} {} {}", old.id, old.date_time, old.url);
+    println!("+++ #{} {} {}", new.id, new.date_time, new.url);
+    for change in changes {
+        println!("{change}");
+    }
+}
+
+/// Reads the secret `name`, e.g. `api_key`, from the source given on the command line: the
+/// value itself, `{name}_file`, `{name}_stdin` if the secret offers it or `{name}_command`
+fn secret(matches: &ArgMatches, name: &str) -> Result<Option<Secret>, Error> {
+    if let Some(key) = matches.get_one::<String>(name) {
+        return Ok(Some(Secret::new(key.to_string())));
+    }
+    if let Some(path) = matches.get_one::<PathBuf>(&format!("{name}_file")) {
+        return Secret::from_file(path).map(Some);
+    }
+    if let Ok(Some(true)) = matches.try_get_one::<bool>(&format!("{name}_stdin")) {
+        return Secret::from_reader(std::io::stdin()).map(Some);
+    }
+    if let Some(command) = matches.get_one::<String>(&format!("{name}_command")) {
+        // The helper may answer per host like git's
+        let api_root = matches
+            .get_one::<String>("api_root")
+            .expect("Default is set in clap");
+        let (protocol, host) = match reqwest::Url::parse(api_root) {
+            Ok(url) => (
+                url.scheme().to_string(),
+                url.host_str().unwrap_or_default().to_string(),
+            ),
+            Err(_) => (String::new(), String::new()),
+        };
+        return Secret::from_helper(command, &protocol, &host).map(Some);
+    }
+    Ok(None)
+}
+
+/// Completes on SIGTERM or Ctrl-C
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate()).expect("Unable to listen for SIGTERM");
+        tokio::select! {
+            _ = terminate.recv() => {}
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Runs a subcommand other than `shell`
+async fn execute(
+    config: &Arc<Config>,
+    endpoints: &[Arc<dyn Endpoint>],
+    matches: &ArgMatches,
+) -> Result<()> {
+    match matches.subcommand() {
+        Some(("diff", sub_matches)) => {
+            let response_diff = match sub_matches.subcommand() {
+                Some(("ids", ids_matches)) => {
+                    // Call function in library with raw parameters
+                    diff(
+                        config,
+                        raw_arg(ids_matches, "old_id"),
+                        raw_arg(ids_matches, "new_id"),
+                    )
+                    .await?
+                }
+                Some((name, request_matches)) => {
+                    let endpoint = endpoint::find(endpoints, name)?;
+                    let raw_values = raw_values(endpoint.as_ref(), request_matches);
+                    diff_latest(config, Request::new(endpoint, raw_values)?).await?
+                }
+                None => unreachable!("subcommand_required prevents `None`"),
+            };
+            print_diff(&response_diff);
+        }
+        Some(("search", sub_matches)) => {
+            // Highlight matches in bold when printing to a terminal
+            let highlight = if std::io::stdout().is_terminal() {
+                ("\x1b[1m".to_string(), "\x1b[0m".to_string())
+            } else {
+                ("[".to_string(), "]".to_string())
+            };
+            let query = SearchQuery::builder()
+                .query(raw_arg(sub_matches, "query"))
+                .request_type(
+                    sub_matches
+                        .get_one::<String>("request_type")
+                        .map(|name| {
+                            endpoint::find(endpoints, name)
+                                .map(|endpoint| endpoint.stored_name().to_string())
+                        })
+                        .transpose()?,
+                )
+                .since(sub_matches.get_one::<DateTime<Utc>>("since").copied())
+                .until(sub_matches.get_one::<DateTime<Utc>>("until").copied())
+                .limit(
+                    *sub_matches
+                        .get_one::<u64>("limit")
+                        .expect("Default is set in clap"),
+                )
+                .highlight(highlight)
+                .build();
+            for result in search(config, &query).await? {
+                println!(
+                    "#{} {} {} {} {}\n    {}",
+                    result.id,
+                    result.date_time,
+                    result.request_type,
+                    result.outcome,
+                    result.url,
+                    result.snippet.trim_end()
+                );
+            }
+        }
+        Some(("daemon", sub_matches)) => {
+            let path = sub_matches
+                .get_one::<PathBuf>("schedule")
+                .expect("Required in clap");
+            let jobs = daemon::load(endpoints, path)?;
+            tracing::info!("Scheduled {} jobs from `{}`", jobs.len(), path.display());
+            daemon::run(config.clone(), jobs, shutdown_signal()).await?;
+        }
+        Some(("serve", sub_matches)) => {
+            let address = sub_matches
+                .get_one::<String>("listen")
+                .expect("Default is set in clap");
+            let listener = server::listen(address).await?;
+            server::serve(
+                config.clone(),
+                endpoints.to_vec(),
+                listener,
+                shutdown_signal(),
+            )
+            .await?;
+        }
+        Some(("db", sub_matches)) => match sub_matches.subcommand() {
+            Some(("prune", prune_matches)) => {
+                let policy = RetentionPolicy::builder()
+                    .keep_days(prune_matches.get_one::<u32>("keep_days").copied())
+                    .keep_last(prune_matches.get_one::<u32>("keep_last").copied())
+                    .keep_failures(!prune_matches.get_flag("prune_failures"))
+                    .vacuum(prune_matches.get_flag("vacuum"))
+                    .build();
+                let report = prune(config, &policy).await?;
+                match report.bytes {
+                    Some(bytes) => println!(
+                        "Pruned {} responses and {} bodies, reclaimed {bytes} bytes",
+                        report.responses, report.bodies
+                    ),
+                    None => println!(
+                        "Pruned {} responses and {} bodies",
+                        report.responses, report.bodies
+                    ),
+                }
+            }
+            _ => unreachable!(
+                "Exhausted list of subcommands and subcommand_required prevents `None`"
+            ),
+        },
+        Some((name, sub_matches)) => {
+            let endpoint = endpoint::find(endpoints, name)?;
+            let raw_values = raw_values(endpoint.as_ref(), sub_matches);
+            let merged = match config.get_pagination() {
+                Some(pagination) => pagination.get_merge(),
+                None => endpoint.pagination().is_some_and(|p| p.get_merge()),
+            };
+            if let Ok(Some(interval)) = sub_matches.try_get_one::<Duration>("watch") {
+                let show_diff = sub_matches.get_flag("watch_diff");
+                let stop = shutdown_signal();
+                let body = body(sub_matches);
+                watch(
+                    config,
+                    endpoint,
+                    raw_values,
+                    body,
+                    *interval,
+                    stop,
+                    |previous, r| {
+                        println!("{} {}", r.date_time, r.url.as_str());
+                        // Diffs need both bodies to be JSON, others are printed whole
+                        let changes = previous.filter(|_| show_diff).and_then(|previous| {
+                            let old = serde_json::from_str(&previous.data).ok()?;
+                            let new = serde_json::from_str(&r.data).ok()?;
+                            Some(diff::diff(&old, &new))
+                        });
+                        match changes {
+                            Some(changes) => changes.iter().for_each(|change| println!("{change}")),
+                            None => println!("{}", r.data),
+                        }
+                    },
+                )
+                .await?;
+                return Ok(());
+            }
+            // Call function in library with raw parameters
+            let r = request(config, endpoint, raw_values, body(sub_matches)).await?;
+            println!("{}", r.url.as_str());
+            // Merged items of all pages aren't stored as such
+            if merged {
+                println!("{}", r.data);
+            }
+        }
+        None => unreachable!("subcommand_required prevents `None`"),
+    }
+    Ok(())
+}
 
-    let matches = command!() // requires `cargo` feature
+/// Command line interface with a subcommand per endpoint
+fn cli(endpoints: &[Arc<dyn Endpoint>]) -> Command {
+    command!() // requires `cargo` feature
         .propagate_version(true)
         .subcommand_required(true)
         .arg_required_else_help(true)
@@ -20,6 +575,111 @@ async fn main() -> Result<()> {
                 .long("api_key")
                 .help("Optional API authentication key"),
         )
+        .arg(
+            Arg::new("api_key_file")
+                .long("api_key_file")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Read the API key from a file"),
+        )
+        .arg(
+            Arg::new("api_key_stdin")
+                .long("api_key_stdin")
+                .action(ArgAction::SetTrue)
+                .help("Read the API key from stdin"),
+        )
+        .arg(
+            Arg::new("api_key_command")
+                .long("api_key_command")
+                .help("Credential helper printing the API key, called like git's with `get`, e.g. `pass-helper`"),
+        )
+        .group(
+            ArgGroup::new("api_key_source")
+                .args(["api_key", "api_key_file", "api_key_stdin", "api_key_command"]),
+        )
+        .arg(
+            Arg::new("auth_scheme")
+                .long("auth_scheme")
+                .value_parser(["header", "bearer", "basic", "query", "oauth2"])
+                .default_value("header")
+                .help("How the API key is sent, basic auth expects the key as `user:password` and OAuth2 as the client secret"),
+        )
+        .arg(
+            Arg::new("auth_name")
+                .long("auth_name")
+                .help("Header or query parameter carrying the API key [default: X-API-Key, api_key]"),
+        )
+        .arg(
+            Arg::new("token_url")
+                .long("token_url")
+                .required_if_eq("auth_scheme", "oauth2")
+                .help("OAuth2 token endpoint of the client credentials grant"),
+        )
+        .arg(
+            Arg::new("client_id")
+                .long("client_id")
+                .required_if_eq("auth_scheme", "oauth2")
+                .help("OAuth2 client id"),
+        )
+        .arg(
+            Arg::new("scope")
+                .long("scope")
+                .help("Optional OAuth2 scope"),
+        )
+        .arg(
+            Arg::new("signing_key")
+                .long("signing_key")
+                .help("Sign requests with an HMAC-SHA256 using this key"),
+        )
+        .arg(
+            Arg::new("signing_key_file")
+                .long("signing_key_file")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Read the signing key from a file"),
+        )
+        .arg(
+            Arg::new("signing_key_command")
+                .long("signing_key_command")
+                .help("Credential helper printing the signing key, called like `api_key_command`"),
+        )
+        .group(
+            ArgGroup::new("signing_key_source")
+                .args(["signing_key", "signing_key_file", "signing_key_command"]),
+        )
+        .arg(
+            Arg::new("signing_canonical")
+                .long("signing_canonical")
+                .requires("signing_key_source")
+                .help("Signed string with `{method}`, `{path}`, `{timestamp}` and `{body}` placeholders [default: {method}\\n{path}\\n{timestamp}\\n{body}]"),
+        )
+        .arg(
+            Arg::new("signature_header")
+                .long("signature_header")
+                .requires("signing_key_source")
+                .default_value("X-Signature")
+                .help("Header carrying the request signature"),
+        )
+        .arg(
+            Arg::new("timestamp_header")
+                .long("timestamp_header")
+                .requires("signing_key_source")
+                .default_value("X-Timestamp")
+                .help("Header carrying the signed unix timestamp"),
+        )
+        .arg(
+            Arg::new("signature_encoding")
+                .long("signature_encoding")
+                .requires("signing_key_source")
+                .value_parser(["hex", "base64"])
+                .default_value("hex")
+                .help("Encoding of the request signature"),
+        )
+        .arg(
+            Arg::new("cache_token")
+                .long("cache_token")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("no_store")
+                .help("Cache OAuth2 tokens in the SQLite database, sharing them between runs"),
+        )
         .arg(
             Arg::new("api_root")
                 .short('r')
@@ -27,6 +687,20 @@ async fn main() -> Result<()> {
                 .default_value("https://httpbin.org/anything")
                 .help("api root for requests"),
         )
+        .arg(
+            Arg::new("error_format")
+                .long("error_format")
+                .alias("error-format")
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .help("How failures are printed to stderr, `json` adds the error kind, family, exit code and sources"),
+        )
+        .arg(
+            Arg::new("timeout")
+                .long("timeout")
+                .value_parser(parse_interval)
+                .help("Fail requests taking longer, e.g. `30s`, with exit code 6"),
+        )
         .arg(
             Arg::new("database_path")
                 .short('d')
@@ -35,6 +709,138 @@ async fn main() -> Result<()> {
                 .value_parser(clap::value_parser!(PathBuf))
                 .help("Database path"),
         )
+        .arg(
+            Arg::new("database_url")
+                .long("database_url")
+                .help("Database url, e.g. `postgres://host/db` or `ndjson://responses.ndjson?max_bytes=1048576`. Overrides `database_path`"),
+        )
+        .arg(
+            Arg::new("no_store")
+                .long("no_store")
+                .alias("no-store")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["database_path", "database_url"])
+                .help("Call the API without storing responses"),
+        )
+        .arg(
+            Arg::new("endpoints")
+                .long("endpoints")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("TOML or YAML file defining endpoints in addition to the built-in ones"),
+        )
+        .arg(
+            Arg::new("openapi")
+                .long("openapi")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("OpenAPI 3 specification, JSON or YAML, whose operations become endpoints"),
+        )
+        .arg(
+            Arg::new("schema")
+                .long("schema")
+                .action(ArgAction::Append)
+                .value_parser(parse_schema)
+                .help("JSON Schema validating responses of a request type, e.g. `get=get.json`"),
+        )
+        .arg(
+            Arg::new("retention_days")
+                .long("retention_days")
+                .value_parser(clap::value_parser!(u32))
+                .help("Automatically prune stored responses older than this many days"),
+        )
+        .arg(
+            Arg::new("retention_last")
+                .long("retention_last")
+                .value_parser(clap::value_parser!(u32))
+                .help("Automatically prune all but this many latest responses per request"),
+        )
+        .arg(
+            Arg::new("paginate")
+                .long("paginate")
+                .value_parser(["link", "cursor", "page"])
+                .help("Follow pages of the response: by `Link` header, by cursor found in the body or by page number"),
+        )
+        .arg(
+            Arg::new("cursor_param")
+                .long("cursor_param")
+                .requires("paginate")
+                .default_value("cursor")
+                .help("Query parameter carrying the cursor"),
+        )
+        .arg(
+            Arg::new("cursor_pointer")
+                .long("cursor_pointer")
+                .requires("paginate")
+                .default_value("/next_cursor")
+                .help("JSON pointer of the next cursor in pages"),
+        )
+        .arg(
+            Arg::new("page_param")
+                .long("page_param")
+                .requires("paginate")
+                .default_value("page")
+                .help("Query parameter carrying the page number"),
+        )
+        .arg(
+            Arg::new("page_start")
+                .long("page_start")
+                .requires("paginate")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("1")
+                .help("Number of the first page"),
+        )
+        .arg(
+            Arg::new("page_size")
+                .long("page_size")
+                .requires("paginate")
+                .value_parser(clap::value_parser!(u64))
+                .help("Requested page size, shorter pages end the pagination"),
+        )
+        .arg(
+            Arg::new("page_size_param")
+                .long("page_size_param")
+                .requires("paginate")
+                .default_value("limit")
+                .help("Query parameter carrying the page size"),
+        )
+        .arg(
+            Arg::new("items_pointer")
+                .long("items_pointer")
+                .requires("paginate")
+                .help("JSON pointer of the items array in pages, e.g. `/items` [default: whole body]"),
+        )
+        .arg(
+            Arg::new("max_pages")
+                .long("max_pages")
+                .requires("paginate")
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .default_value("100")
+                .help("Stop following pages after this many"),
+        )
+        .arg(
+            Arg::new("merge_pages")
+                .long("merge_pages")
+                .requires("paginate")
+                .action(ArgAction::SetTrue)
+                .help("Merge the items of all pages into one JSON array"),
+        )
+        .arg(
+            Arg::new("redact_header")
+                .long("redact_header")
+                .action(ArgAction::Append)
+                .help("Request header whose value is redacted from logs"),
+        )
+        .arg(
+            Arg::new("redact_query")
+                .long("redact_query")
+                .action(ArgAction::Append)
+                .help("Query parameter whose value is redacted from stored urls and logs"),
+        )
+        .arg(
+            Arg::new("redact_json")
+                .long("redact_json")
+                .action(ArgAction::Append)
+                .help("JSON pointer whose value is redacted from stored bodies, `*` matches any key or index, e.g. `/users/*/token`"),
+        )
         .arg(
             Arg::new("verbose")
                 .short('v')
@@ -52,36 +858,140 @@ async fn main() -> Result<()> {
                 .conflicts_with("verbose")
                 .help("Silences output"),
         )
+        .subcommands(subcommands(endpoints))
         .subcommand(
-            Command::new("get")
-                .visible_alias("g")
-                .about("get API request")
-                .arg(arg!(<brand_id> "Valid brand id")),
+            Command::new("daemon")
+                .about("run requests on cron schedules until SIGTERM or Ctrl-C")
+                .arg(
+                    arg!(<schedule> "TOML or YAML file of jobs with cron schedules")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                ),
         )
         .subcommand(
-            Command::new("last_run")
-                .visible_alias("l")
-                .about("last run API request")
-                .arg(arg!(<brand_id> "Valid brand id"))
-                .arg(arg!(<location_id> "Valid location id")),
+            Command::new("serve")
+                .about("serve the endpoints and stored responses as a local REST API")
+                .arg(
+                    Arg::new("listen")
+                        .long("listen")
+                        .default_value("127.0.0.1:8080")
+                        .help("Address the server listens on"),
+                ),
         )
         .subcommand(
-            Command::new("run")
-                .visible_alias("r")
-                .about("run API request")
-                .arg(arg!(<brand_id> "Valid brand id"))
-                .arg(arg!(<location_id> "Valid location id")),
+            Command::new("shell")
+                .about("interactive shell with history and completion, keeping connections open"),
         )
         .subcommand(
-            Command::new("specific")
-                .visible_alias("s")
-                .about("specific API request")
-                .arg(arg!(<brand_id> "Valid brand id"))
-                .arg(arg!(<location_id> "Valid location id"))
-                .arg(arg!(<from_date> "Unix timestamp(ms)"))
-                .arg(arg!(<to_date> "Unix timestamp(ms) >= `from_date`")),
+            Command::new("completions")
+                .about("print the script completing subcommands, options and stored ids in a shell")
+                .arg(
+                    arg!(<shell> "Shell to complete in")
+                        .value_parser(Shells::builtins().names().collect::<Vec<_>>()),
+                ),
         )
-        .get_matches();
+        .subcommand(
+            Command::new("manpage")
+                .about("print the man page, or write pages of all subcommands into a directory")
+                .arg(
+                    Arg::new("out_dir")
+                        .long("out_dir")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("Directory the pages are written to"),
+                ),
+        )
+        .subcommand(
+            Command::new("tui")
+                .about("browse, filter, re-issue and diff stored responses in a terminal UI")
+                .arg(
+                    Arg::new("limit")
+                        .long("limit")
+                        .default_value("500")
+                        .value_parser(clap::value_parser!(u64))
+                        .help("Number of latest responses loaded"),
+                ),
+        )
+}
+
+/// Exit code of a failure, the one of its error family if it stems from an [`Error`]
+fn exit_code(report: &Report) -> u8 {
+    report
+        .chain()
+        .find_map(|e| e.downcast_ref::<Error>())
+        .map_or(1, |e| e.family().exit_code())
+}
+
+/// Failure as printed with `--error_format json`. Kind and family are null if it doesn't
+/// stem from an [`Error`]
+fn error_json(report: &Report) -> serde_json::Value {
+    let error = report.chain().find_map(|e| e.downcast_ref::<Error>());
+    let mut messages = error::messages(report.as_ref()).into_iter();
+    json!({
+        "error": {
+            "kind": error.map(Error::kind),
+            "family": error.map(|e| e.family().to_string()),
+            "exit_code": exit_code(report),
+            "message": messages.next(),
+            "sources": messages.collect::<Vec<_>>(),
+        }
+    })
+}
+
+/// Whether failures are printed as JSON. Read before parsing the command line, so invalid
+/// usage is reported as asked for too
+fn json_errors() -> bool {
+    early_arg("error_format")
+        .or_else(|| early_arg("error-format"))
+        .is_some_and(|format| format == "json")
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(report) if json_errors() => {
+            eprintln!("{}", error_json(&report));
+            ExitCode::from(exit_code(&report))
+        }
+        Err(report) => {
+            // Printed like `main` returning the report would
+            eprintln!("Error: {report:?}");
+            ExitCode::from(exit_code(&report))
+        }
+    }
+}
+
+async fn run() -> Result<()> {
+    color_eyre::install()?;
+
+    let mut endpoints = endpoint::builtin();
+    if let Some(path) = early_file("openapi") {
+        openapi::load(&mut endpoints, &path)?;
+    }
+    if let Some(path) = early_file("endpoints") {
+        definition::load(&mut endpoints, &path)?;
+    }
+    // Answers the calls of completion scripts, before anything is printed
+    CompleteEnv::with_factory(|| cli(&endpoints)).complete();
+    let matches = match cli(&endpoints).try_get_matches() {
+        Ok(matches) => matches,
+        // Help and version are printed as usual
+        Err(e) if json_errors() && e.use_stderr() => {
+            let rendered = e.render().to_string();
+            let message = rendered.lines().next().unwrap_or_default();
+            let usage = json!({
+                "error": {
+                    "kind": "Usage",
+                    "family": "usage",
+                    "exit_code": e.exit_code(),
+                    "message": message.trim_start_matches("error: "),
+                    "sources": [],
+                }
+            });
+            eprintln!("{usage}");
+            std::process::exit(e.exit_code());
+        }
+        Err(e) => e.exit(),
+    };
 
     // Enable tracing subscriber and get verbosity level from cli parameters
     let level = match (matches.get_flag("quiet"), matches.get_count("verbose")) {
@@ -95,96 +1005,318 @@ async fn main() -> Result<()> {
         // Always show errors
         (_, _) => Level::ERROR,
     };
-    tracing_subscriber::fmt()
+    let subscriber = tracing_subscriber::fmt()
         // all spans/events with a level higher than TRACE (e.g, info, warn, etc.)
         // will be written to stdout.
-        .with_max_level(level)
-        // sets this to be the default, global collector for this application.
-        .init();
+        .with_max_level(level);
+    // sets this to be the default, global collector for this application.
+    // The terminal UI owns the screen, its errors are shown in the status line instead
+    if matches.subcommand_name() == Some("tui") {
+        subscriber.with_writer(std::io::sink).init();
+    } else {
+        subscriber.init();
+    }
 
     // Extract parameters for config
     let config = Config::builder()
-        .api_key(matches.get_one::<String>("api_key").cloned())
+        .api_key(secret(&matches, "api_key")?)
+        .auth_scheme({
+            let name = matches.get_one::<String>("auth_name").cloned();
+            match matches.get_one::<String>("auth_scheme").map(String::as_str) {
+                Some("bearer") => AuthScheme::Bearer,
+                Some("basic") => AuthScheme::Basic,
+                Some("query") => AuthScheme::Query(name.unwrap_or("api_key".to_string())),
+                Some("oauth2") => AuthScheme::OAuth2(
+                    ClientCredentials::builder()
+                        .token_url(
+                            matches
+                                .get_one::<String>("token_url")
+                                .expect("Required for oauth2 in clap")
+                                .to_string(),
+                        )
+                        .client_id(
+                            matches
+                                .get_one::<String>("client_id")
+                                .expect("Required for oauth2 in clap")
+                                .to_string(),
+                        )
+                        .scope(matches.get_one::<String>("scope").cloned())
+                        .cache_in_db(matches.get_flag("cache_token"))
+                        .build(),
+                ),
+                _ => name.map_or_else(AuthScheme::default, AuthScheme::Header),
+            }
+        })
         .api_root(
             matches
                 .get_one::<String>("api_root")
                 .expect("Default is set in clap")
                 .to_string(),
         )
+        .timeout(matches.get_one::<Duration>("timeout").copied())
+        .signer(secret(&matches, "signing_key")?.map(|key| {
+            let signer = HmacSigner::builder()
+                .key(key)
+                .signature_header(
+                    matches
+                        .get_one::<String>("signature_header")
+                        .expect("Default is set in clap")
+                        .to_string(),
+                )
+                .timestamp_header(
+                    matches
+                        .get_one::<String>("timestamp_header")
+                        .expect("Default is set in clap")
+                        .to_string(),
+                )
+                .encoding(
+                    match matches
+                        .get_one::<String>("signature_encoding")
+                        .map(String::as_str)
+                    {
+                        Some("base64") => SignatureEncoding::Base64,
+                        _ => SignatureEncoding::Hex,
+                    },
+                );
+            Arc::new(match matches.get_one::<String>("signing_canonical") {
+                // Allow escaped line breaks on the command line
+                Some(canonical) => signer.canonical(canonical.replace("\\n", "\n")).build(),
+                None => signer.build(),
+            }) as Arc<dyn RequestSigner>
+        }))
         .db_path(
             matches
                 .get_one::<PathBuf>("database_path")
                 .expect("Default is set in clap")
                 .to_owned(),
         )
+        .db_url(matches.get_one::<String>("database_url").cloned())
+        .no_store(matches.get_flag("no_store"))
+        .response_schemas(
+            matches
+                .get_many::<(String, PathBuf)>("schema")
+                .unwrap_or_default()
+                .cloned()
+                .collect::<HashMap<_, _>>(),
+        )
+        .retention(
+            match (
+                matches.get_one::<u32>("retention_days"),
+                matches.get_one::<u32>("retention_last"),
+            ) {
+                (None, None) => None,
+                (keep_days, keep_last) => Some(
+                    RetentionPolicy::builder()
+                        .keep_days(keep_days.copied())
+                        .keep_last(keep_last.copied())
+                        .build(),
+                ),
+            },
+        )
+        .pagination(matches.get_one::<String>("paginate").map(|strategy| {
+            let string = |id: &str| {
+                matches
+                    .get_one::<String>(id)
+                    .expect("Default is set in clap")
+                    .to_string()
+            };
+            let strategy = match strategy.as_str() {
+                "cursor" => PageStrategy::Cursor {
+                    param: string("cursor_param"),
+                    pointer: string("cursor_pointer"),
+                },
+                "page" => PageStrategy::Page {
+                    param: string("page_param"),
+                    start: *matches
+                        .get_one::<u64>("page_start")
+                        .expect("Default is set in clap"),
+                    size_param: string("page_size_param"),
+                    size: matches.get_one::<u64>("page_size").copied(),
+                },
+                _ => PageStrategy::Link,
+            };
+            Pagination::builder()
+                .strategy(strategy)
+                .max_pages(
+                    *matches
+                        .get_one::<u32>("max_pages")
+                        .expect("Default is set in clap"),
+                )
+                .items_pointer(matches.get_one::<String>("items_pointer").cloned())
+                .merge(matches.get_flag("merge_pages"))
+                .build()
+        }))
+        .redaction(
+            Redaction::builder()
+                .headers(
+                    matches
+                        .get_many::<String>("redact_header")
+                        .unwrap_or_default()
+                        .cloned()
+                        .collect(),
+                )
+                .query_params(
+                    matches
+                        .get_many::<String>("redact_query")
+                        .unwrap_or_default()
+                        .cloned()
+                        .collect(),
+                )
+                .json_pointers(
+                    matches
+                        .get_many::<String>("redact_json")
+                        .unwrap_or_default()
+                        .cloned()
+                        .collect(),
+                )
+                .build(),
+        )
         .build();
 
+    // Shared by the commands of a shell session or terminal UI, the daemon's jobs and the
+    // server's requests
+    let config = Arc::new(config);
     match matches.subcommand() {
-        Some(("get", sub_matches)) => {
-            let raw_brand_id = sub_matches
-                .get_one::<String>("brand_id")
-                .expect("String parses any input")
-                .to_owned();
-            // Call function in library with raw parameters
-            let r = get(&config, raw_brand_id).await?;
-            println!("{}", r.url.as_str());
+        Some(("shell", _)) => shell::run(config, endpoints).await,
+        Some(("completions", sub_matches)) => {
+            let shell = sub_matches
+                .get_one::<String>("shell")
+                .expect("Required in clap");
+            write_completions(shell, &mut std::io::stdout())
         }
-        Some(("last_run", sub_matches)) => {
-            let raw_brand_id = sub_matches
-                .get_one::<String>("brand_id")
-                .expect("String parses any input")
-                .to_owned();
-            let raw_location_id = sub_matches
-                .get_one::<String>("location_id")
-                .expect("String parses any input")
-                .to_owned();
-            // Call function in library with raw parameters
-            let r = last_run(&config, raw_brand_id, raw_location_id).await?;
-            println!("{}", r.url.as_str());
+        Some(("manpage", sub_matches)) => {
+            let command = cli(&endpoints);
+            match sub_matches.get_one::<PathBuf>("out_dir") {
+                Some(out_dir) => clap_mangen::generate_to(command, out_dir)?,
+                None => clap_mangen::Man::new(command).render(&mut std::io::stdout())?,
+            }
+            Ok(())
         }
-        Some(("run", sub_matches)) => {
-            let raw_brand_id = sub_matches
-                .get_one::<String>("brand_id")
-                .expect("String parses any input")
-                .to_owned();
-            let raw_location_id = sub_matches
-                .get_one::<String>("location_id")
-                .expect("String parses any input")
-                .to_owned();
-            // Call function in library with raw parameters
-            let r = run(&config, raw_brand_id, raw_location_id).await?;
-            println!("{}", r.url.as_str());
+        Some(("tui", sub_matches)) => {
+            let limit = sub_matches
+                .get_one::<u64>("limit")
+                .expect("Default is set in clap");
+            tui::run(config, endpoints, *limit).await
         }
-        Some(("specific", sub_matches)) => {
-            let raw_brand_id = sub_matches
-                .get_one::<String>("brand_id")
-                .expect("String parses any input")
-                .to_owned();
-            let raw_location_id = sub_matches
-                .get_one::<String>("location_id")
-                .expect("String parses any input")
-                .to_owned();
-            let raw_from_date = sub_matches
-                .get_one::<String>("from_date")
-                .expect("String parses any input")
-                .to_owned();
-            let raw_to_date = sub_matches
-                .get_one::<String>("to_date")
-                .expect("String parses any input")
-                .to_owned();
+        _ => execute(&config, &endpoints, &matches).await,
+    }
+}
 
-            // Call function in library with raw parameters
-            let r = specific(
-                &config,
-                raw_brand_id,
-                raw_location_id,
-                raw_from_date,
-                raw_to_date,
-            )
-            .await?;
-            println!("{}", r.url.as_str());
+#[test]
+fn cli_test() {
+    cli(&endpoint::builtin()).debug_assert();
+    for shell in Shells::builtins().names() {
+        let mut script = Vec::new();
+        write_completions(shell, &mut script).unwrap();
+        assert!(String::from_utf8(script).unwrap().contains("COMPLETE"));
+    }
+}
+
+#[test]
+fn reserved_test() {
+    // Defined endpoints may use any name or parameter the cli doesn't
+    let endpoints = endpoint::builtin();
+    let is_endpoint = |command: &Command| {
+        endpoints
+            .iter()
+            .find(|endpoint| endpoint.name() == command.get_name())
+    };
+    let mut cli = cli(&endpoints);
+    cli.build();
+    let mut shell = shell::command(&endpoints);
+    shell.build();
+    let diff = cli.find_subcommand("diff").unwrap().clone();
+    for command in cli
+        .get_subcommands()
+        .chain(shell.get_subcommands())
+        .chain(diff.get_subcommands())
+    {
+        match is_endpoint(command) {
+            Some(endpoint) => {
+                let parameters = endpoint.parameters();
+                for arg in command.get_arguments().filter(|arg| {
+                    !parameters
+                        .iter()
+                        .any(|parameter| parameter.name == arg.get_id().as_str())
+                }) {
+                    let id = arg.get_id().as_str();
+                    assert!(definition::RESERVED_PARAMETERS.contains(&id), "{id}");
+                }
+            }
+            None => {
+                for name in std::iter::once(command.get_name()).chain(command.get_all_aliases()) {
+                    assert!(definition::RESERVED_NAMES.contains(&name), "{name}");
+                }
+            }
         }
-        _ => unreachable!("Exhausted list of subcommands and subcommand_required prevents `None`"),
     }
-    Ok(())
+}
+
+#[test]
+fn exit_code_test() {
+    let report = Report::new(Error::DatabaseResponseNotFound(
+        1,
+        "test.sqlite3".to_string(),
+    ))
+    .wrap_err("Reading response");
+    assert_eq!(exit_code(&report), 7);
+    assert_eq!(exit_code(&color_eyre::eyre::eyre!("Terminal failed")), 1);
+}
+
+#[test]
+fn error_json_test() {
+    let source = "x".parse::<u64>().unwrap_err();
+    let report = Report::new(Error::ValidateInvalidTimestamp(
+        "specific".to_string(),
+        "x".to_string(),
+        source,
+    ));
+    let json = error_json(&report.wrap_err("Reading arguments"));
+    assert_eq!(json["error"]["kind"], "ValidateInvalidTimestamp");
+    assert_eq!(json["error"]["family"], "validation");
+    assert_eq!(json["error"]["exit_code"], 3);
+    assert_eq!(json["error"]["message"], "Reading arguments");
+    assert_eq!(json["error"]["sources"].as_array().unwrap().len(), 2);
+
+    let json = error_json(&color_eyre::eyre::eyre!("Terminal failed"));
+    assert!(json["error"]["kind"].is_null());
+    assert_eq!(json["error"]["exit_code"], 1);
+}
+
+#[test]
+fn parse_interval_test() {
+    assert_eq!(parse_interval("5m"), Ok(Duration::from_secs(300)));
+    assert_eq!(parse_interval("30"), Ok(Duration::from_secs(30)));
+    assert!(parse_interval("0s").is_err());
+    assert!(parse_interval("6000000000000000h").is_err());
+
+    // Requests with a body usually trigger work, repeating them isn't offered
+    let command = cli(&endpoint::builtin());
+    let has_watch = |name| {
+        command
+            .find_subcommand(name)
+            .unwrap()
+            .get_arguments()
+            .any(|arg| arg.get_id() == "watch")
+    };
+    assert!(has_watch("last_run"));
+    assert!(!has_watch("start_run"));
+}
+
+#[test]
+fn secret_test() {
+    let path = std::env::temp_dir().join("apicommand_signing_key_test");
+    std::fs::write(&path, "k3y\n").unwrap();
+    let matches = cli(&endpoint::builtin())
+        .try_get_matches_from([
+            "apicommand",
+            "--signing_key_file",
+            path.to_str().unwrap(),
+            "get",
+            "brand",
+        ])
+        .unwrap();
+    let key = secret(&matches, "signing_key").unwrap().unwrap();
+    assert_eq!(key.expose(), "k3y");
+    assert!(secret(&matches, "api_key").unwrap().is_none());
 }
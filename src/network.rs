@@ -1,63 +1,256 @@
 use crate::{
-    configuration::Config,
+    configuration::{AuthScheme, Config, Pagination},
+    endpoint::{Request, RequestBody},
     error::Error,
-    validate::{GetParameters, LastRunParameters, RunParameters, SpecificParameters},
+    oauth, pagination, redact,
+    secret::{Secret, REDACTED},
 };
 
 use chrono::{DateTime, Utc};
 use reqwest::{
-    header::{HeaderMap, HeaderValue},
-    StatusCode, Url,
+    header::{HeaderMap, HeaderName, HeaderValue, ACCEPT},
+    Client, RequestBuilder, Response, StatusCode, Url,
 };
 use strum::Display;
 
 #[derive(Debug)]
 pub struct ApiResponse {
     pub date_time: DateTime<Utc>,
-    pub request_type: RequestType,
+    pub request: Request,
     pub status: StatusCode,
     pub url: Url,
+    /// Body sent with the request, if any
+    pub request_body: Option<String>,
     pub data: String,
+    pub outcome: Outcome,
 }
 
+/// Result of checking a response before it is stored
 #[derive(Debug, Display)]
-pub enum RequestType {
-    Get(GetParameters),
-    LastRun(LastRunParameters),
-    Run(RunParameters),
-    Specific(SpecificParameters),
+pub enum Outcome {
+    Success,
+    SchemaViolation(Vec<String>),
 }
-pub async fn send_api_request(
+impl Outcome {
+    /// Details stored alongside the outcome, if any
+    pub fn detail(&self) -> Option<String> {
+        match self {
+            Outcome::Success => None,
+            Outcome::SchemaViolation(violations) => Some(violations.join("\n")),
+        }
+    }
+}
+
+/// Full url of a request below the configured api root
+pub fn url(config: &Config, request: &Request) -> String {
+    format!("{}/{}", config.get_api_root(), request.path())
+}
+
+/// Url responses of a request are stored with, normalized like the url of the response and
+/// including the redacted api key of the query auth scheme
+pub fn stored_url(config: &Config, request: &Request) -> String {
+    let url = url(config, request);
+    let Ok(mut parsed) = Url::parse(&url) else {
+        return url;
+    };
+    if let (AuthScheme::Query(name), Some(_)) = (config.get_auth_scheme(), config.get_api_key()) {
+        parsed.query_pairs_mut().append_pair(name, REDACTED);
+    }
+    parsed.to_string()
+}
+
+/// Adds the api key to a request as configured by the auth scheme,
+/// `refresh` fetches a new OAuth2 token instead of using the cached one
+async fn authenticate(
     config: &Config,
-    request_type: RequestType,
-) -> Result<ApiResponse, Error> {
-    // Enables to handle different request types in various ways
-    let tail = match &request_type {
-        RequestType::Get(p) => p.to_string(),
-        RequestType::LastRun(p) => p.to_string(),
-        RequestType::Run(p) => p.to_string(),
-        RequestType::Specific(p) => p.to_string(),
+    request: RequestBuilder,
+    refresh: bool,
+) -> Result<RequestBuilder, Error> {
+    if let AuthScheme::OAuth2(credentials) = config.get_auth_scheme() {
+        return Ok(request.bearer_auth(oauth::token(config, credentials, refresh).await?));
+    }
+    let Some(key) = config.get_api_key().map(Secret::expose) else {
+        return Ok(request);
     };
-    let url = format!("{}/{}", config.get_api_root(), tail);
-    let client = reqwest::Client::new();
+    Ok(match config.get_auth_scheme() {
+        AuthScheme::Header(name) => {
+            let mut value = HeaderValue::from_str(key)
+                .map_err(|e| Error::NetworkInvalidHeaderValue(name.clone(), e))?;
+            // Sensitive values are left out of debug output
+            value.set_sensitive(true);
+            let header = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| Error::NetworkInvalidHeaderName(name.clone(), e))?;
+            request.header(header, value)
+        }
+        AuthScheme::Bearer => request.bearer_auth(key),
+        AuthScheme::Basic => match key.split_once(':') {
+            Some((user, password)) => request.basic_auth(user, Some(password)),
+            None => request.basic_auth(key, None::<&str>),
+        },
+        AuthScheme::Query(name) => request.query(&[(name, key)]),
+        AuthScheme::OAuth2(_) => unreachable!("Handled above"),
+    })
+}
 
-    let mut headers = HeaderMap::new();
+/// Sends an authenticated request to `target`, returning the response and its redacted url
+async fn send(
+    config: &Config,
+    client: &Client,
+    request: &Request,
+    target: &str,
+    refresh: bool,
+) -> Result<(Response, Url), Error> {
+    let endpoint = request.endpoint();
+    let builder = endpoint.headers().into_iter().fold(
+        client
+            .request(endpoint.method(), target)
+            .header(ACCEPT, endpoint.response_format().mime_type()),
+        |builder, (name, value)| builder.header(name, value),
+    );
+    let builder = match request.body() {
+        Some(RequestBody::Json(value)) => builder.json(value),
+        Some(RequestBody::Form(fields)) => builder.form(fields),
+        None => builder,
+    };
+    let builder = match config.get_timeout() {
+        Some(timeout) => builder.timeout(timeout),
+        None => builder,
+    };
+    let mut request = authenticate(config, builder, refresh)
+        .await?
+        .build()
+        .map_err(|e| request_error(config, target, e))?;
+    if let Some(signer) = config.get_signer() {
+        signer.sign(&mut request)?;
+    }
+    // Errors carry the request url, which must not leak the api key either
+    let redacted = redact::url(config, request.url());
+    tracing::debug!(
+        url = %redacted,
+        headers = ?redact::headers(config, request.headers()),
+        "Sending API request"
+    );
 
-    if let Some(key) = config.get_api_key() {
-        headers.append("X-API-Key", HeaderValue::from_str(key)?);
+    match client.execute(request).await {
+        Ok(response) => Ok((response, redacted)),
+        Err(error) => Err(request_error(config, target, error)),
     }
+}
+
+/// Error of a request to `target`, the url of `error` may include the api key and is
+/// replaced by the redacted target
+fn request_error(config: &Config, target: &str, error: reqwest::Error) -> Error {
+    let url = Url::parse(target)
+        .map(|url| redact::url(config, &url).to_string())
+        .unwrap_or_else(|_| target.to_string());
+    Error::NetworkRequest(url, error.without_url())
+}
 
-    let response = client.get(url).headers(headers).send().await?;
+/// Sends a request to `target` and returns the response with its headers, retrying once
+/// with a new OAuth2 token if it was rejected
+async fn fetch(
+    config: &Config,
+    client: &Client,
+    request: &Request,
+    target: &str,
+) -> Result<(ApiResponse, HeaderMap), Error> {
+    let (mut response, mut redacted) = send(config, client, request, target, false).await?;
+    // Cached tokens may be revoked before they expire, retry once with a new one
+    if response.status() == StatusCode::UNAUTHORIZED
+        && matches!(config.get_auth_scheme(), AuthScheme::OAuth2(_))
+    {
+        (response, redacted) = send(config, client, request, target, true).await?;
+    }
+    // Requests triggering work may be answered with e.g. `201 Created` or `202 Accepted`
     match response.status() {
-        StatusCode::OK => Ok(ApiResponse {
-            date_time: Utc::now(),
-            request_type,
-            status: response.status(),
-            url: response.url().clone(),
-            data: response.text().await?,
-        }),
+        status if status.is_success() => {
+            let headers = response.headers().clone();
+            let api_response = ApiResponse {
+                date_time: Utc::now(),
+                request_body: request.body().map(RequestBody::content),
+                request: request.clone(),
+                status,
+                url: redact::url(config, response.url()),
+                data: response
+                    .text()
+                    .await
+                    .map_err(|e| request_error(config, target, e))?,
+                outcome: Outcome::Success,
+            };
+            Ok((api_response, headers))
+        }
         _ => Err(Error::NetworkUnexpectedStatusCode(
+            request.endpoint().name().to_string(),
+            redacted.to_string(),
             response.status().to_string(),
         )),
     }
 }
+
+pub async fn send_api_request(config: &Config, request: Request) -> Result<ApiResponse, Error> {
+    let client = config.get_connections().get_client();
+    let (api_response, _) = fetch(config, client, &request, &url(config, &request)).await?;
+    Ok(api_response)
+}
+
+/// Sends the request and follows its pages until they are exhausted or `max_pages` is
+/// reached, returning every page
+pub async fn send_pages(
+    config: &Config,
+    request: Request,
+    pagination: &Pagination,
+) -> Result<Vec<ApiResponse>, Error> {
+    let client = config.get_connections().get_client();
+    let url = url(config, &request);
+    let parsed = Url::parse(&url).map_err(|e| Error::NetworkInvalidUrl(url, e))?;
+    let mut target = pagination::first_url(pagination, parsed);
+    let mut pages = Vec::new();
+    loop {
+        let (api_response, headers) = fetch(config, client, &request, target.as_str()).await?;
+        let index = pages.len() as u64;
+        let next = pagination::next_url(pagination, &target, &headers, &api_response.data, index);
+        pages.push(api_response);
+        match next {
+            // A repeated page would be followed forever
+            Some(next) if next == target => break,
+            Some(_) if pages.len() as u64 >= u64::from(pagination.get_max_pages()) => {
+                tracing::warn!(
+                    max_pages = pagination.get_max_pages(),
+                    "Stopped following pages at the limit"
+                );
+                break;
+            }
+            Some(next) => target = next,
+            None => break,
+        }
+    }
+    Ok(pages)
+}
+
+#[tokio::test]
+async fn oauth2_refresh_test() {
+    use crate::{configuration::ClientCredentials, endpoint::Get, test_support::stub_server};
+    use std::sync::Arc;
+
+    // The api only accepts the second token, the first one counts as revoked
+    let (root, _) = stub_server(|request, count| match request.starts_with("POST") {
+        true => (200, format!(r#"{{"access_token": "token-{count}"}}"#)),
+        false if request.contains("Bearer token-2") => (200, "{}".to_string()),
+        false => (401, "{}".to_string()),
+    })
+    .await;
+    let credentials = ClientCredentials::builder()
+        .token_url(format!("{root}/token"))
+        .client_id("client".to_string())
+        .build();
+    let config = Config::builder()
+        .api_root(root)
+        .api_key(Some("secret".to_string()))
+        .auth_scheme(AuthScheme::OAuth2(credentials))
+        .db_path(std::path::PathBuf::from("test.sqlite3"))
+        .build();
+
+    let request = Request::new(Arc::new(Get), vec!["brand".to_string()]).unwrap();
+    let response = send_api_request(&config, request).await.unwrap();
+    assert_eq!(response.status, StatusCode::OK);
+}
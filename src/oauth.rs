@@ -0,0 +1,177 @@
+use crate::{
+    configuration::{ClientCredentials, Config},
+    database,
+    error::Error,
+};
+
+use chrono::{DateTime, Duration, Utc};
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::{collections::HashMap, sync::LazyLock};
+use tokio::sync::Mutex;
+
+/// Lifetime assumed if the token endpoint doesn't return `expires_in`
+const DEFAULT_EXPIRES_IN: i64 = 3600;
+/// Tokens are refreshed this many seconds before they expire
+const EXPIRY_MARGIN: i64 = 30;
+
+/// Access token of the client credentials grant
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct Token {
+    pub access_token: String,
+    pub expires_at: DateTime<Utc>,
+}
+impl Token {
+    fn is_valid(&self) -> bool {
+        self.expires_at - Duration::seconds(EXPIRY_MARGIN) > Utc::now()
+    }
+}
+
+/// Tokens of this process keyed by token url and client id. The lock is held while a token
+/// is fetched, so concurrent requests share one token
+static TOKENS: LazyLock<Mutex<HashMap<(String, String), Token>>> = LazyLock::new(Default::default);
+
+/// Returns a valid access token, fetching a new one if none is cached or `refresh` is set
+pub async fn token(
+    config: &Config,
+    credentials: &ClientCredentials,
+    refresh: bool,
+) -> Result<String, Error> {
+    let key = (
+        credentials.get_token_url().to_string(),
+        credentials.get_client_id().to_string(),
+    );
+    let mut tokens = TOKENS.lock().await;
+
+    if !refresh {
+        if let Some(token) = tokens.get(&key).filter(|token| token.is_valid()) {
+            return Ok(token.access_token.clone());
+        }
+        if credentials.get_cache_in_db() {
+            let storage = database::open(config).await?;
+            let cached = storage.read_token(&key.0, &key.1).await?;
+            if let Some(token) = cached.filter(Token::is_valid) {
+                let access_token = token.access_token.clone();
+                tokens.insert(key, token);
+                return Ok(access_token);
+            }
+        }
+    }
+
+    let token = fetch(config, credentials).await?;
+    if credentials.get_cache_in_db() {
+        let storage = database::open(config).await?;
+        storage.write_token(&key.0, &key.1, &token).await?;
+    }
+    let access_token = token.access_token.clone();
+    tokens.insert(key, token);
+    Ok(access_token)
+}
+
+/// Requests a token from the token endpoint, the api key is the client secret
+async fn fetch(config: &Config, credentials: &ClientCredentials) -> Result<Token, Error> {
+    let mut form = vec![
+        ("grant_type", "client_credentials"),
+        ("client_id", credentials.get_client_id()),
+    ];
+    if let Some(secret) = config.get_api_key() {
+        form.push(("client_secret", secret.expose()));
+    }
+    if let Some(scope) = credentials.get_scope() {
+        form.push(("scope", scope));
+    }
+
+    tracing::debug!(url = credentials.get_token_url(), "Fetching OAuth2 token");
+    let builder = config
+        .get_connections()
+        .get_client()
+        .post(credentials.get_token_url())
+        .form(&form);
+    let builder = match config.get_timeout() {
+        Some(timeout) => builder.timeout(timeout),
+        None => builder,
+    };
+    let token_url = credentials.get_token_url();
+    let request_error = |e: reqwest::Error| Error::NetworkRequest(token_url.to_string(), e);
+    let response = builder.send().await.map_err(request_error)?;
+    if response.status() != StatusCode::OK {
+        return Err(Error::AuthTokenStatus(
+            token_url.to_string(),
+            response.status().to_string(),
+        ));
+    }
+
+    let body: Value = response.json().await.map_err(request_error)?;
+    let invalid = |field| Error::AuthInvalidTokenResponse(token_url.to_string(), field);
+    let access_token = body["access_token"]
+        .as_str()
+        .ok_or_else(|| invalid("access_token"))?;
+    let expires_in = match &body["expires_in"] {
+        Value::Null => DEFAULT_EXPIRES_IN,
+        expires_in => expires_in.as_i64().ok_or_else(|| invalid("expires_in"))?,
+    };
+    Ok(Token {
+        access_token: access_token.to_string(),
+        expires_at: Utc::now() + Duration::seconds(expires_in),
+    })
+}
+
+#[tokio::test]
+async fn token_cache_test() {
+    use crate::test_support::stub_server;
+    use std::sync::atomic::Ordering;
+
+    let (root, requests) = stub_server(|request, count| {
+        if !request.contains("grant_type=client_credentials&client_id=client&client_secret=secret")
+        {
+            return (400, r#"{"error": "invalid_client"}"#.to_string());
+        }
+        let body = format!(r#"{{"access_token": "token-{count}", "expires_in": 3600}}"#);
+        (200, body)
+    })
+    .await;
+    let path = std::env::temp_dir().join("apicommand_oauth_test.sqlite3");
+    let _ = std::fs::remove_file(&path);
+    let credentials = ClientCredentials::builder()
+        .token_url(format!("{root}/token"))
+        .client_id("client".to_string())
+        .cache_in_db(true)
+        .build();
+    let config = Config::builder()
+        .api_root(root.clone())
+        .api_key(Some("secret".to_string()))
+        .db_path(path)
+        .build();
+
+    assert_eq!(
+        token(&config, &credentials, false).await.unwrap(),
+        "token-0"
+    );
+    assert_eq!(
+        token(&config, &credentials, false).await.unwrap(),
+        "token-0"
+    );
+    assert_eq!(token(&config, &credentials, true).await.unwrap(), "token-1");
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+    // A new process finds the token in the database
+    let key = (format!("{root}/token"), "client".to_string());
+    TOKENS.lock().await.remove(&key);
+    assert_eq!(
+        token(&config, &credentials, false).await.unwrap(),
+        "token-1"
+    );
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+    // No database is created just for tokens
+    let path = std::env::temp_dir().join("apicommand_oauth_no_store_test.sqlite3");
+    let _ = std::fs::remove_file(&path);
+    let config = Config::builder()
+        .api_root(root)
+        .api_key(Some("secret".to_string()))
+        .db_path(path.clone())
+        .no_store(true)
+        .build();
+    assert_eq!(token(&config, &credentials, true).await.unwrap(), "token-2");
+    assert!(!path.exists());
+}
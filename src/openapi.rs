@@ -0,0 +1,356 @@
+use crate::{
+    definition::{
+        self, DefinedEndpoint, EndpointDefinition, FormatDefinition, ParameterDefinition,
+        ParameterType,
+    },
+    endpoint::Endpoint,
+    error::Error,
+};
+
+use reqwest::Method;
+use serde_json::{Map, Value};
+use std::{path::Path, sync::Arc};
+
+/// Operations of a path item, in the order endpoints are derived
+const METHODS: [&str; 7] = ["get", "put", "post", "delete", "options", "head", "patch"];
+
+/// Parses an OpenAPI 3 document, JSON for `.json` files and YAML otherwise
+fn parse(path: &Path, raw: &str) -> Result<Value, Error> {
+    let parse_error = |e: String| Error::EndpointParse(path.into(), e);
+    let spec: Value = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => serde_json::from_str(raw).map_err(|e| parse_error(e.to_string()))?,
+        _ => serde_yaml::from_str(raw).map_err(|e| parse_error(e.to_string()))?,
+    };
+    match spec.get("openapi").and_then(Value::as_str) {
+        Some(version) if version.starts_with("3.") => Ok(spec),
+        _ => Err(parse_error("expected an OpenAPI 3 document".to_string())),
+    }
+}
+
+/// Follows local `$ref`s like `#/components/parameters/brand_id`
+fn resolve<'a>(spec: &'a Value, mut value: &'a Value) -> &'a Value {
+    // Bounded, as references may form a cycle
+    for _ in 0..16 {
+        match value
+            .get("$ref")
+            .and_then(Value::as_str)
+            .and_then(|reference| reference.strip_prefix('#'))
+            .and_then(|pointer| spec.pointer(pointer))
+        {
+            Some(target) => value = target,
+            None => break,
+        }
+    }
+    value
+}
+
+/// Subcommand name from an operation id, e.g. `getLastRun` becomes `get_last_run`
+fn snake_case(raw: &str) -> String {
+    let mut name = String::new();
+    let mut previous_lower = false;
+    for c in raw.chars() {
+        if c.is_uppercase() && previous_lower {
+            name.push('_');
+        }
+        previous_lower = c.is_lowercase() || c.is_ascii_digit();
+        match c {
+            '-' | '.' | ' ' | '/' => name.push('_'),
+            _ => name.extend(c.to_lowercase()),
+        }
+    }
+    name
+}
+
+/// Validation rules of a path parameter from its schema
+fn parameter(spec: &Value, raw: &Value) -> ParameterDefinition {
+    let schema = resolve(spec, raw.get("schema").unwrap_or(&Value::Null));
+    let string = |key: &str| schema.get(key).and_then(Value::as_str);
+    let number = |key: &str| schema.get(key).and_then(Value::as_f64);
+    let length = |key: &str| {
+        schema
+            .get(key)
+            .and_then(Value::as_u64)
+            .and_then(|length| usize::try_from(length).ok())
+    };
+
+    let mut one_of: Vec<String> = schema
+        .get("enum")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .map(|value| match value {
+            Value::String(value) => value.clone(),
+            value => value.to_string(),
+        })
+        .collect();
+    let kind = match string("type") {
+        Some("integer") => ParameterType::Integer,
+        Some("number") => ParameterType::Number,
+        Some("boolean") if one_of.is_empty() => {
+            one_of = vec!["true".to_string(), "false".to_string()];
+            ParameterType::String
+        }
+        _ => ParameterType::String,
+    };
+
+    ParameterDefinition {
+        name: raw["name"].as_str().unwrap_or_default().to_string(),
+        help: raw
+            .get("description")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        kind,
+        pattern: string("pattern").map(str::to_string),
+        search_pattern: true,
+        min_length: length("minLength"),
+        max_length: length("maxLength"),
+        min: number("minimum"),
+        max: number("maximum"),
+        one_of,
+    }
+}
+
+/// Format and JSON Schema of the successful response of an operation
+fn response(spec: &Value, operation: &Value) -> (FormatDefinition, Option<Value>) {
+    let responses = operation.get("responses").and_then(Value::as_object);
+    let success = responses.and_then(|responses| {
+        ["200", "201", "2XX", "default"]
+            .into_iter()
+            .find_map(|status| responses.get(status))
+    });
+    let Some(content) = success
+        .map(|success| resolve(spec, success))
+        .and_then(|success| success.get("content"))
+        .and_then(Value::as_object)
+    else {
+        return (FormatDefinition::Json, None);
+    };
+
+    match content
+        .iter()
+        .find(|(mime_type, _)| mime_type.contains("json"))
+    {
+        Some((_, media)) => {
+            // Schemas may refer to components, resolved relative to the schema root
+            let schema = media.get("schema").map(|schema| match schema {
+                Value::Object(schema) => {
+                    let mut schema = schema.clone();
+                    if let Some(components) = spec.get("components") {
+                        schema
+                            .entry("components")
+                            .or_insert_with(|| components.clone());
+                    }
+                    Value::Object(schema)
+                }
+                schema => schema.clone(),
+            });
+            (FormatDefinition::Json, schema)
+        }
+        None if content
+            .keys()
+            .any(|mime_type| mime_type.starts_with("text/")) =>
+        {
+            (FormatDefinition::Text, None)
+        }
+        None => (FormatDefinition::Json, None),
+    }
+}
+
+/// Endpoint definition of an operation, path parameters become cli arguments in the order
+/// they appear in the path
+fn operation(
+    spec: &Value,
+    path: &str,
+    method: &str,
+    path_parameters: &[Value],
+    operation: &Value,
+) -> EndpointDefinition {
+    let mut parameters: Vec<&Value> = Vec::new();
+    // Operation parameters override those of the path item
+    for raw in path_parameters.iter().chain(
+        operation
+            .get("parameters")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten(),
+    ) {
+        let raw = resolve(spec, raw);
+        if raw.get("in").and_then(Value::as_str) != Some("path") {
+            continue;
+        }
+        parameters.retain(|existing| existing.get("name") != raw.get("name"));
+        parameters.push(raw);
+    }
+    let mut parameters: Vec<ParameterDefinition> = parameters
+        .into_iter()
+        .map(|raw| parameter(spec, raw))
+        .collect();
+    parameters.sort_by_key(|parameter| path.find(&format!("{{{}}}", parameter.name)));
+
+    let name = match operation.get("operationId").and_then(Value::as_str) {
+        Some(operation_id) => snake_case(operation_id),
+        None => std::iter::once(method)
+            .chain(
+                path.split('/')
+                    .filter(|segment| !segment.is_empty() && !segment.starts_with('{')),
+            )
+            .map(snake_case)
+            .collect::<Vec<_>>()
+            .join("_"),
+    };
+    let about = ["summary", "description"]
+        .into_iter()
+        .find_map(|key| operation.get(key).and_then(Value::as_str))
+        .and_then(|about| about.lines().next())
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{} {path}", method.to_uppercase()));
+    let (response_format, response_schema) = response(spec, operation);
+
+    EndpointDefinition {
+        name,
+        stored_name: None,
+        about,
+        alias: None,
+        method: method.to_uppercase(),
+        path: path.trim_start_matches('/').to_string(),
+        parameters,
+        headers: Default::default(),
+        response_format,
+        response_schema,
+        pagination: None,
+    }
+}
+
+/// Loads endpoints from the operations of an OpenAPI 3 document. Operations with the
+/// method and path of an existing endpoint replace it, keeping its name, stored name and
+/// alias, others are appended
+pub fn load(endpoints: &mut Vec<Arc<dyn Endpoint>>, path: &Path) -> Result<(), Error> {
+    let raw = std::fs::read_to_string(path).map_err(|e| Error::EndpointRead(path.into(), e))?;
+    let spec = parse(path, &raw)?;
+    let empty = Map::new();
+    let paths = spec
+        .get("paths")
+        .and_then(Value::as_object)
+        .unwrap_or(&empty);
+
+    for (template, path_item) in paths {
+        let path_item = resolve(&spec, path_item);
+        let path_parameters = path_item
+            .get("parameters")
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        for method in METHODS {
+            let Some(raw_operation) = path_item.get(method) else {
+                continue;
+            };
+            let mut definition = operation(&spec, template, method, path_parameters, raw_operation);
+
+            let covered = endpoints.iter().position(|endpoint| {
+                endpoint.path() == definition.path
+                    && Method::from_bytes(definition.method.as_bytes()).ok()
+                        == Some(endpoint.method())
+            });
+            match covered {
+                Some(index) => {
+                    let covered = &endpoints[index];
+                    definition.name = covered.name().to_string();
+                    definition.stored_name = Some(covered.stored_name().to_string());
+                    definition.alias = covered.alias().map(str::to_string);
+                    if !["summary", "description"]
+                        .iter()
+                        .any(|key| raw_operation.get(key).is_some())
+                    {
+                        definition.about = covered.about().to_string();
+                    }
+                    endpoints[index] = Arc::new(DefinedEndpoint::new(definition)?);
+                }
+                None => definition::push(endpoints, DefinedEndpoint::new(definition)?)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn load_test() {
+    use crate::{
+        endpoint::{builtin, find, Request},
+        schema::violations,
+    };
+
+    let path = std::env::temp_dir().join("apicommand_openapi_test.json");
+    let spec = serde_json::json!({
+        "openapi": "3.0.3",
+        "info": { "title": "test", "version": "1" },
+        "paths": {
+            "/get/{brand_id}": {
+                "parameters": [{ "$ref": "#/components/parameters/brand_id" }],
+                "get": { "responses": { "200": { "description": "brand" } } }
+            },
+            "/stock/{brand_id}/{count}": {
+                "get": {
+                    "operationId": "getStockLevels",
+                    "summary": "stock levels",
+                    "parameters": [
+                        {
+                            "name": "count",
+                            "in": "path",
+                            "required": true,
+                            "schema": { "type": "integer", "minimum": 1, "maximum": 10 }
+                        },
+                        { "$ref": "#/components/parameters/brand_id" },
+                        { "name": "page", "in": "query", "schema": { "type": "integer" } }
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "stock",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "$ref": "#/components/schemas/Stock" }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+        "components": {
+            "parameters": {
+                "brand_id": {
+                    "name": "brand_id",
+                    "in": "path",
+                    "required": true,
+                    "schema": { "type": "string", "pattern": "^[a-z]+$", "maxLength": 8 }
+                }
+            },
+            "schemas": {
+                "Stock": {
+                    "type": "object",
+                    "required": ["count"],
+                    "properties": { "count": { "type": "integer" } }
+                }
+            }
+        }
+    });
+    std::fs::write(&path, spec.to_string()).unwrap();
+    let mut endpoints = builtin();
+    load(&mut endpoints, &path).unwrap();
+
+    // Covered built-in endpoints keep their names but use the rules of the specification
+    let get = find(&endpoints, "get").unwrap();
+    assert_eq!((get.stored_name(), get.alias()), ("Get", Some("g")));
+    assert!(Request::new(get.clone(), vec!["brand".to_string()]).is_ok());
+    assert!(Request::new(get, vec!["Brand1".to_string()]).is_err());
+
+    let stock = find(&endpoints, "get_stock_levels").unwrap();
+    assert_eq!(stock.about(), "stock levels");
+    let request = Request::new(stock.clone(), vec!["brand".to_string(), "3".to_string()]);
+    assert_eq!(request.unwrap().path(), "stock/brand/3");
+    assert!(Request::new(stock.clone(), vec!["brand".to_string(), "11".to_string()]).is_err());
+
+    let schema = stock.response_schema().unwrap();
+    assert!(violations(&schema, r#"{"count": 1}"#).unwrap().is_empty());
+    assert_eq!(violations(&schema, r#"{"count": "1"}"#).unwrap().len(), 1);
+}
@@ -0,0 +1,232 @@
+use crate::{
+    configuration::{PageStrategy, Pagination},
+    error::Error,
+    network::ApiResponse,
+};
+
+use reqwest::{
+    header::{HeaderMap, LINK},
+    Url,
+};
+use serde_json::Value;
+
+/// Url with the query parameter `name` set to `value`, replacing earlier values
+fn with_query(url: &Url, name: &str, value: &str) -> Url {
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| key != name)
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    let mut url = url.clone();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(pairs)
+        .append_pair(name, value);
+    url
+}
+
+/// Url of the first page, requesting the page size if the strategy has one
+pub fn first_url(pagination: &Pagination, url: Url) -> Url {
+    match pagination.get_strategy() {
+        PageStrategy::Page {
+            param,
+            start,
+            size_param,
+            size,
+        } => {
+            let url = with_query(&url, param, &start.to_string());
+            match size {
+                Some(size) => with_query(&url, size_param, &size.to_string()),
+                None => url,
+            }
+        }
+        PageStrategy::Link | PageStrategy::Cursor { .. } => url,
+    }
+}
+
+/// Targets and `;`-separated params of the links in a `Link` header value. Targets are
+/// taken from between `<>` first, as urls may contain commas
+fn links(mut value: &str) -> Vec<(&str, &str)> {
+    let mut links = Vec::new();
+    while let Some(start) = value.find('<') {
+        let Some(length) = value[start..].find('>') else {
+            break;
+        };
+        let target = &value[start + 1..start + length];
+        let rest = &value[start + length + 1..];
+        // Params end at the first comma outside a quoted string
+        let mut quoted = false;
+        let end = rest
+            .char_indices()
+            .find(|&(_, c)| {
+                quoted ^= c == '"';
+                c == ',' && !quoted
+            })
+            .map_or(rest.len(), |(end, _)| end);
+        links.push((target, &rest[..end]));
+        value = &rest[end..];
+    }
+    links
+}
+
+/// Target of the `rel="next"` link of `Link` headers
+fn next_link(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(LINK)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(links)
+        .find_map(|(target, params)| {
+            params
+                .split(';')
+                .filter_map(|param| param.trim().strip_prefix("rel="))
+                .any(|rel| {
+                    rel.trim_matches('"')
+                        .split_whitespace()
+                        .any(|rel| rel == "next")
+                })
+                .then_some(target.trim())
+        })
+}
+
+/// Items of a page, `None` if the page has no items array
+fn items(pagination: &Pagination, page: &Value) -> Option<Vec<Value>> {
+    let items = match pagination.get_items_pointer() {
+        Some(pointer) => page.pointer(pointer)?,
+        None => page,
+    };
+    items.as_array().cloned()
+}
+
+/// Url of the page following the `index`th page requested from `url`, `None` once the
+/// pages are exhausted. Links to another scheme, host or port aren't followed, as requests
+/// carry the credentials of the API
+pub fn next_url(
+    pagination: &Pagination,
+    url: &Url,
+    headers: &HeaderMap,
+    data: &str,
+    index: u64,
+) -> Option<Url> {
+    match pagination.get_strategy() {
+        PageStrategy::Link => {
+            let next = url.join(next_link(headers)?).ok()?;
+            if next.origin() != url.origin() {
+                tracing::warn!(
+                    origin = next.origin().ascii_serialization(),
+                    "Stopped following pages at a link to another origin"
+                );
+                return None;
+            }
+            Some(next)
+        }
+        PageStrategy::Cursor { param, pointer } => {
+            let page = serde_json::from_str::<Value>(data).ok()?;
+            let cursor = match page.pointer(pointer)? {
+                Value::String(cursor) if !cursor.is_empty() => cursor.clone(),
+                Value::Number(cursor) => cursor.to_string(),
+                _ => return None,
+            };
+            Some(with_query(url, param, &cursor))
+        }
+        PageStrategy::Page {
+            param, start, size, ..
+        } => {
+            let page = serde_json::from_str::<Value>(data).ok()?;
+            let count = items(pagination, &page)?.len() as u64;
+            if count == 0 || size.is_some_and(|size| count < size) {
+                return None;
+            }
+            Some(with_query(url, param, &(start + index + 1).to_string()))
+        }
+    }
+}
+
+/// Items of all pages merged into one JSON array. Pages without items array are added
+/// as a whole
+pub fn merge(pagination: &Pagination, pages: &[ApiResponse]) -> Result<String, Error> {
+    let mut merged = Vec::new();
+    for api_response in pages {
+        let page = serde_json::from_str::<Value>(&api_response.data)
+            .map_err(|e| Error::NetworkInvalidPageJson(api_response.url.to_string(), e))?;
+        match items(pagination, &page) {
+            Some(items) => merged.extend(items),
+            None if pagination.get_items_pointer().is_none() => merged.push(page),
+            None => {}
+        }
+    }
+    Ok(Value::Array(merged).to_string())
+}
+
+#[test]
+fn next_url_test() {
+    let url = Url::parse("http://localhost/specific/b?from=1").unwrap();
+    let headers = HeaderMap::new();
+
+    let page = Pagination::builder()
+        .strategy(PageStrategy::Page {
+            param: "page".to_string(),
+            start: 1,
+            size_param: "limit".to_string(),
+            size: Some(2),
+        })
+        .items_pointer(Some("/items".to_string()))
+        .build();
+    let first = first_url(&page, url.clone());
+    assert_eq!(
+        first.as_str(),
+        "http://localhost/specific/b?from=1&page=1&limit=2"
+    );
+    let next = next_url(&page, &first, &headers, r#"{"items": [1, 2]}"#, 0).unwrap();
+    assert_eq!(
+        next.as_str(),
+        "http://localhost/specific/b?from=1&limit=2&page=2"
+    );
+    assert!(next_url(&page, &next, &headers, r#"{"items": [3]}"#, 1).is_none());
+
+    let cursor = Pagination::builder()
+        .strategy(PageStrategy::Cursor {
+            param: "cursor".to_string(),
+            pointer: "/next".to_string(),
+        })
+        .build();
+    let next = next_url(&cursor, &url, &headers, r#"{"next": "c2"}"#, 0).unwrap();
+    assert_eq!(
+        next.as_str(),
+        "http://localhost/specific/b?from=1&cursor=c2"
+    );
+    assert!(next_url(&cursor, &next, &headers, r#"{"next": null}"#, 1).is_none());
+
+    let link = Pagination::builder().strategy(PageStrategy::Link).build();
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        LINK,
+        r#"<https://other/last>; rel="last", </specific/b?page=2>; rel="next""#
+            .parse()
+            .unwrap(),
+    );
+    let next = next_url(&link, &url, &headers, "", 0).unwrap();
+    assert_eq!(next.as_str(), "http://localhost/specific/b?page=2");
+    assert!(next_url(&link, &next, &HeaderMap::new(), "", 1).is_none());
+
+    // Commas separate links only outside of targets and quoted params
+    headers.insert(
+        LINK,
+        r#"</specific/b?ids=1,2>; rel="prev"; title="a, b", </specific/b?ids=3,4&page=3>; rel="next""#
+            .parse()
+            .unwrap(),
+    );
+    let next = next_url(&link, &url, &headers, "", 0).unwrap();
+    assert_eq!(next.as_str(), "http://localhost/specific/b?ids=3,4&page=3");
+
+    // Credentials must not be sent to another origin
+    for target in [
+        "https://localhost/specific/b?page=2",
+        "http://other/specific/b?page=2",
+        "http://localhost:8080/specific/b?page=2",
+    ] {
+        let mut headers = HeaderMap::new();
+        headers.insert(LINK, format!(r#"<{target}>; rel="next""#).parse().unwrap());
+        assert!(next_url(&link, &url, &headers, "", 0).is_none());
+    }
+}
@@ -0,0 +1,221 @@
+use crate::{
+    configuration::{AuthScheme, Config},
+    endpoint::RequestBody,
+    network::ApiResponse,
+    secret::REDACTED,
+};
+
+use reqwest::{header::HeaderMap, Url};
+use serde_json::Value;
+
+/// Headers always carrying credentials
+const SENSITIVE_HEADERS: [&str; 3] = ["authorization", "proxy-authorization", "cookie"];
+
+/// Url with the values of redacted query parameters replaced, including the api key of
+/// the query auth scheme
+pub fn url(config: &Config, url: &Url) -> Url {
+    let redacted = |key: &str| {
+        matches!(config.get_auth_scheme(), AuthScheme::Query(name) if name == key)
+            || config
+                .get_redaction()
+                .get_query_params()
+                .iter()
+                .any(|name| name == key)
+    };
+    if !url.query_pairs().any(|(key, _)| redacted(&key)) {
+        return url.clone();
+    }
+
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(key, value)| match redacted(&key) {
+            true => (key.into_owned(), REDACTED.to_string()),
+            false => (key.into_owned(), value.into_owned()),
+        })
+        .collect();
+    let mut url = url.clone();
+    url.query_pairs_mut().clear().extend_pairs(pairs);
+    url
+}
+
+/// Header names and values safe to log, credentials and redacted headers are replaced
+pub fn headers(config: &Config, headers: &HeaderMap) -> Vec<(String, String)> {
+    let auth_header = match config.get_auth_scheme() {
+        AuthScheme::Header(name) => Some(name.as_str()),
+        _ => None,
+    };
+    let redacted = |key: &str| {
+        SENSITIVE_HEADERS.contains(&key)
+            || auth_header.is_some_and(|name| name.eq_ignore_ascii_case(key))
+            || config
+                .get_redaction()
+                .get_headers()
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(key))
+    };
+    headers
+        .iter()
+        .map(|(key, value)| {
+            let value = match redacted(key.as_str()) || value.is_sensitive() {
+                true => REDACTED.to_string(),
+                false => String::from_utf8_lossy(value.as_bytes()).to_string(),
+            };
+            (key.to_string(), value)
+        })
+        .collect()
+}
+
+/// Body with the values at redacted JSON pointer paths replaced. Bodies that aren't JSON
+/// or have no redacted values are returned unchanged
+pub fn body(config: &Config, data: &str) -> String {
+    redacted_json(config, data).unwrap_or_else(|| data.to_string())
+}
+
+/// JSON body with the values at redacted JSON pointer paths replaced, `None` if it isn't
+/// JSON or has no redacted values
+fn redacted_json(config: &Config, data: &str) -> Option<String> {
+    let pointers = config.get_redaction().get_json_pointers();
+    if pointers.is_empty() {
+        return None;
+    }
+    let mut value = serde_json::from_str::<Value>(data).ok()?;
+
+    let mut changed = false;
+    for pointer in pointers {
+        let segments: Vec<String> = pointer
+            .split('/')
+            .skip(1)
+            .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+            .collect();
+        changed |= redact_value(&mut value, &segments);
+    }
+    changed.then(|| value.to_string())
+}
+
+/// Replaces the value at the path, `*` matches every key or index
+fn redact_value(value: &mut Value, segments: &[String]) -> bool {
+    let Some((segment, rest)) = segments.split_first() else {
+        *value = Value::String(REDACTED.to_string());
+        return true;
+    };
+    let children: Vec<&mut Value> = match value {
+        Value::Object(map) if segment == "*" => map.values_mut().collect(),
+        Value::Object(map) => map.get_mut(segment).into_iter().collect(),
+        Value::Array(array) if segment == "*" => array.iter_mut().collect(),
+        Value::Array(array) => segment
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| array.get_mut(index))
+            .into_iter()
+            .collect(),
+        _ => Vec::new(),
+    };
+    // Every matching child is redacted, no short circuit
+    let mut changed = false;
+    for child in children {
+        changed |= redact_value(child, rest);
+    }
+    changed
+}
+
+/// Whether the value of a query parameter or form field is redacted, not counting the api
+/// key of the query auth scheme
+fn redacted_param(config: &Config, key: &str) -> bool {
+    config
+        .get_redaction()
+        .get_query_params()
+        .iter()
+        .any(|name| name == key)
+}
+
+/// Request body as stored. JSON bodies are redacted like response bodies, form fields
+/// like query parameters
+fn request_body(config: &Config, request_body: &RequestBody) -> String {
+    match request_body {
+        RequestBody::Json(_) => body(config, &request_body.content()),
+        RequestBody::Form(fields) => {
+            let fields = fields
+                .iter()
+                .map(|(key, value)| match redacted_param(config, key) {
+                    true => (key.clone(), REDACTED.to_string()),
+                    false => (key.clone(), value.clone()),
+                })
+                .collect();
+            RequestBody::Form(fields).content()
+        }
+    }
+}
+
+/// Whether the redaction rules replaced values of a stored request, which then can't be
+/// sent again. The api key of the query auth scheme is added again when sending
+pub fn is_redacted(config: &Config, url: &Url, request_body: Option<&RequestBody>) -> bool {
+    url.query_pairs()
+        .any(|(key, _)| redacted_param(config, &key))
+        || match request_body {
+            Some(body @ RequestBody::Json(_)) => redacted_json(config, &body.content()).is_some(),
+            Some(RequestBody::Form(fields)) => {
+                fields.iter().any(|(key, _)| redacted_param(config, key))
+            }
+            None => false,
+        }
+}
+
+/// Scrubs a response and the request body sent before they are stored
+pub fn response(config: &Config, api_response: &mut ApiResponse) {
+    api_response.url = url(config, &api_response.url);
+    api_response.request_body = api_response
+        .request
+        .body()
+        .map(|body| request_body(config, body));
+    api_response.data = body(config, &api_response.data);
+}
+
+#[test]
+fn redact_test() {
+    use crate::configuration::Redaction;
+
+    let config = Config::builder()
+        .api_root("http://localhost".to_string())
+        .api_key(Some("secret".to_string()))
+        .auth_scheme(AuthScheme::Query("key".to_string()))
+        .db_path(std::path::PathBuf::from("test.sqlite3"))
+        .redaction(
+            Redaction::builder()
+                .headers(vec!["X-Session".to_string()])
+                .query_params(vec!["session".to_string()])
+                .json_pointers(vec!["/token".to_string(), "/users/*/a~1b".to_string()])
+                .build(),
+        )
+        .build();
+    let url_with_key =
+        Url::parse("http://localhost/get/brand?page=2&key=secret&session=s").unwrap();
+    assert_eq!(
+        url(&config, &url_with_key).as_str(),
+        "http://localhost/get/brand?page=2&key=REDACTED&session=REDACTED"
+    );
+    assert!(!format!("{config:?}").contains("secret"));
+
+    let mut header_map = HeaderMap::new();
+    header_map.insert("x-session", "s".parse().unwrap());
+    header_map.insert("accept", "*/*".parse().unwrap());
+    assert_eq!(
+        headers(&config, &header_map),
+        vec![
+            ("x-session".to_string(), REDACTED.to_string()),
+            ("accept".to_string(), "*/*".to_string())
+        ]
+    );
+
+    let data = r#"{"token": "t", "users": [{"a/b": 1}, {"c": 2}]}"#;
+    assert_eq!(
+        body(&config, data),
+        r#"{"token":"REDACTED","users":[{"a/b":"REDACTED"},{"c":2}]}"#
+    );
+    assert_eq!(body(&config, r#"{"other": 1}"#), r#"{"other": 1}"#);
+
+    let form = RequestBody::Form(vec![
+        ("session".to_string(), "s".to_string()),
+        ("page".to_string(), "2".to_string()),
+    ]);
+    assert_eq!(request_body(&config, &form), "session=REDACTED&page=2");
+}
@@ -0,0 +1,124 @@
+use crate::{
+    configuration::Config,
+    endpoint::ResponseFormat,
+    error::Error,
+    network::{ApiResponse, Outcome},
+};
+
+use jsonschema::ValidationError;
+use serde_json::Value;
+use std::path::Path;
+
+fn load(path: &Path) -> Result<Value, Error> {
+    let raw = std::fs::read_to_string(path).map_err(|e| Error::SchemaRead(path.into(), e))?;
+    serde_json::from_str(&raw).map_err(|e| Error::SchemaParse(path.into(), e))
+}
+
+/// Collects every violation of `schema` by `body`, an empty list means the body is valid.
+/// Fails with the reason `schema` itself is invalid
+pub fn violations(schema: &Value, body: &str) -> Result<Vec<String>, String> {
+    describe_violations(schema, body, |e| format!("`{}`: {}", e.instance_path(), e))
+}
+
+/// Violations naming only the location and the failed keyword, as the messages of
+/// [`violations`] include the offending values
+fn keyword_violations(schema: &Value, body: &str) -> Result<Vec<String>, String> {
+    describe_violations(schema, body, |e| {
+        format!("`{}`: fails `{}`", e.instance_path(), e.kind().keyword())
+    })
+}
+
+fn describe_violations(
+    schema: &Value,
+    body: &str,
+    describe: fn(&ValidationError) -> String,
+) -> Result<Vec<String>, String> {
+    let validator = jsonschema::validator_for(schema).map_err(|e| e.to_string())?;
+
+    // A body that isn't JSON at all can't satisfy any schema
+    let instance = match serde_json::from_str::<Value>(body) {
+        Ok(instance) => instance,
+        Err(e) => return Ok(vec![format!("Body is not valid JSON: {e}")]),
+    };
+
+    Ok(validator
+        .iter_errors(&instance)
+        .map(|e| describe(&e))
+        .collect())
+}
+
+/// Validates the JSON response body against the schema configured for its endpoint, or
+/// else the one its definition provides, and records any violations in the response outcome
+pub fn check(config: &Config, api_response: &mut ApiResponse) -> Result<(), Error> {
+    let endpoint = api_response.request.endpoint();
+    if endpoint.response_format() != ResponseFormat::Json {
+        return Ok(());
+    }
+    let schema = match config.get_response_schema(endpoint.name()) {
+        Some(path) => load(path)?,
+        None => match endpoint.response_schema() {
+            Some(schema) => schema,
+            None => return Ok(()),
+        },
+    };
+    // Values of a body with redacted paths must not end up in the stored outcome or errors
+    let violations = match config.get_redaction().get_json_pointers().is_empty() {
+        true => violations(&schema, &api_response.data),
+        false => keyword_violations(&schema, &api_response.data),
+    }
+    .map_err(|e| Error::SchemaInvalid(endpoint.name().to_string(), e))?;
+
+    if !violations.is_empty() {
+        api_response.outcome = Outcome::SchemaViolation(violations);
+    }
+    Ok(())
+}
+
+#[test]
+fn violations_test() {
+    let schema = serde_json::json!({
+        "type": "object",
+        "properties": { "brand_id": { "type": "string" } },
+        "required": ["brand_id"]
+    });
+
+    assert!(violations(&schema, r#"{"brand_id": "abc"}"#)
+        .unwrap()
+        .is_empty());
+
+    let found = violations(&schema, r#"{"brand_id": 42}"#).unwrap();
+    assert_eq!(found.len(), 1);
+    assert!(found[0].starts_with("`/brand_id`"));
+
+    assert_eq!(violations(&schema, "not json").unwrap().len(), 1);
+}
+
+#[test]
+fn check_redacted_test() {
+    use crate::{configuration::Redaction, database::test_response};
+    use std::collections::HashMap;
+
+    let path = std::env::temp_dir().join("apicommand_schema_test.json");
+    std::fs::write(
+        &path,
+        r#"{"properties": {"token": {"type": "string", "pattern": "^x"}}}"#,
+    )
+    .unwrap();
+    let redaction = Redaction::builder()
+        .json_pointers(vec!["/token".to_string()])
+        .build();
+    let config = Config::builder()
+        .api_root("http://localhost".to_string())
+        .api_key(None::<String>)
+        .db_path(std::path::PathBuf::from("test.sqlite3"))
+        .response_schemas(HashMap::from([("get".to_string(), path)]))
+        .redaction(redaction)
+        .build();
+
+    let mut api_response = test_response(r#"{"token": "s3cr3t"}"#);
+    check(&config, &mut api_response).unwrap();
+    let Outcome::SchemaViolation(violations) = api_response.outcome else {
+        panic!("Expected a schema violation");
+    };
+    assert_eq!(violations, vec!["`/token`: fails `pattern`"]);
+}
@@ -0,0 +1,123 @@
+use crate::error::Error;
+
+use std::{
+    fmt::{Debug, Display},
+    io::{Read, Write},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Placeholder replacing secrets in logs and stored urls
+pub const REDACTED: &str = "REDACTED";
+
+/// Secret value, e.g. an api key, redacted in `Debug` and `Display` output
+#[derive(Clone, PartialEq)]
+pub struct Secret(String);
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Self(value)
+    }
+    /// The secret itself, only to be used where it is sent
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    /// Reads the secret from a file, ignoring a trailing line break
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| Error::SecretRead(path.display().to_string(), e))?;
+        Self::parse(&content, &path.display().to_string())
+    }
+
+    /// Reads the secret from a reader such as stdin, ignoring a trailing line break
+    pub fn from_reader(mut reader: impl Read) -> Result<Self, Error> {
+        let mut content = String::new();
+        reader
+            .read_to_string(&mut content)
+            .map_err(|e| Error::SecretRead("stdin".to_string(), e))?;
+        Self::parse(&content, "stdin")
+    }
+
+    /// Asks a credential helper like git's: runs `<command> get` through the shell with
+    /// `protocol=...` and `host=...` lines on stdin and reads the `password=...` line of
+    /// its output. Helpers printing only the secret are supported as well
+    pub fn from_helper(command: &str, protocol: &str, host: &str) -> Result<Self, Error> {
+        let read_error = |e| Error::SecretRead(command.to_string(), e);
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{command} get"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(read_error)?;
+        child
+            .stdin
+            .take()
+            .expect("Stdin is piped")
+            .write_all(format!("protocol={protocol}\nhost={host}\n\n").as_bytes())
+            // Helpers printing a fixed secret may exit without reading their input
+            .or_else(|e| match e.kind() {
+                std::io::ErrorKind::BrokenPipe => Ok(()),
+                _ => Err(e),
+            })
+            .map_err(read_error)?;
+        let output = child.wait_with_output().map_err(read_error)?;
+        if !output.status.success() {
+            return Err(Error::SecretHelperFailed(
+                command.to_string(),
+                output.status.to_string(),
+            ));
+        }
+
+        let output = String::from_utf8_lossy(&output.stdout);
+        match output
+            .lines()
+            .find_map(|line| line.strip_prefix("password="))
+        {
+            Some(password) => Self::parse(password, command),
+            None => Self::parse(&output, command),
+        }
+    }
+
+    fn parse(content: &str, source: &str) -> Result<Self, Error> {
+        let value = content.trim_end_matches(['\n', '\r']);
+        match value.is_empty() {
+            true => Err(Error::SecretEmpty(source.to_string())),
+            false => Ok(Self(value.to_string())),
+        }
+    }
+}
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+impl Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret({REDACTED})")
+    }
+}
+impl Display for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+#[test]
+fn secret_test() {
+    let secret = Secret::from_reader("key\n".as_bytes()).unwrap();
+    assert_eq!(secret.expose(), "key");
+    assert_eq!(format!("{secret} {secret:?}"), "REDACTED Secret(REDACTED)");
+    assert!(Secret::from_reader("\n".as_bytes()).is_err());
+
+    let helper = "f() { grep -q host=example.com && echo username=u && echo password=pw; }; f";
+    let secret = Secret::from_helper(helper, "https", "example.com").unwrap();
+    assert_eq!(secret.expose(), "pw");
+    assert_eq!(
+        Secret::from_helper("echo plain #", "https", "example.com")
+            .unwrap()
+            .expose(),
+        "plain"
+    );
+    assert!(Secret::from_helper("false", "https", "example.com").is_err());
+}
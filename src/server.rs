@@ -0,0 +1,306 @@
+use crate::{
+    configuration::Config,
+    database::StoredResponse,
+    endpoint::{self, Endpoint, RequestBody},
+    error::Error,
+};
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header::CONTENT_TYPE, HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{any, get},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{future::Future, sync::Arc};
+use tokio::net::TcpListener;
+
+#[derive(Clone)]
+struct AppState {
+    config: Arc<Config>,
+    endpoints: Arc<Vec<Arc<dyn Endpoint>>>,
+}
+
+/// Status code of an error, the message and its sources are sent as JSON body
+fn status(error: &Error) -> StatusCode {
+    match error {
+        Error::ValidateUnknownEndpoint(_)
+        | Error::DatabaseResponseNotFound(..)
+        | Error::DatabaseNotEnoughResponses(..) => StatusCode::NOT_FOUND,
+        Error::ValidateInvalidBrandId(..)
+        | Error::ValidateInvalidLocationId(..)
+        | Error::ValidateInvalidTimestamp(..)
+        | Error::ValidateInvalidDateTimeSpan(..)
+        | Error::ValidateInvalidResponseId(_)
+        | Error::ValidateParameterCount(..)
+        | Error::ValidateInvalidParameter(..)
+        | Error::ValidateUnexpectedBody(_)
+        | Error::ValidateInvalidSearchQuery(..)
+        | Error::ServerInvalidJson(_) => StatusCode::BAD_REQUEST,
+        Error::ServerMethodNotAllowed(..) => StatusCode::METHOD_NOT_ALLOWED,
+        Error::DatabaseUnsupportedOperation(..) => StatusCode::NOT_IMPLEMENTED,
+        Error::NetworkRequest(_, e) if e.is_timeout() => StatusCode::GATEWAY_TIMEOUT,
+        // The API failed or answered unexpectedly
+        Error::AuthTokenStatus(..)
+        | Error::AuthInvalidTokenResponse(..)
+        | Error::NetworkRequest(..)
+        | Error::NetworkInvalidPageJson(..)
+        | Error::NetworkUnexpectedStatusCode(..)
+        | Error::SchemaViolation(..) => StatusCode::BAD_GATEWAY,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        let message = self.message();
+        let status = status(&self);
+        if status.is_server_error() {
+            tracing::warn!("{message}");
+        }
+        (status, Json(json!({ "error": message }))).into_response()
+    }
+}
+
+fn timestamp(date_time: &DateTime<Utc>) -> String {
+    date_time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+/// Body embedded as JSON where possible
+fn embed(data: &str) -> Value {
+    serde_json::from_str(data).unwrap_or_else(|_| Value::String(data.to_string()))
+}
+
+fn stored_json(stored: &StoredResponse) -> Value {
+    json!({
+        "id": stored.id,
+        "date_time": timestamp(&stored.date_time),
+        "request_type": stored.request_type,
+        "url": stored.url,
+        "outcome": stored.outcome,
+        "request_body": stored.request_body.as_deref().map(embed),
+        "data": embed(&stored.data),
+    })
+}
+
+/// Request body of a call, form encoded or JSON
+fn body(headers: &HeaderMap, bytes: &Bytes) -> Result<Option<RequestBody>, Error> {
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+    let form = headers
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/x-www-form-urlencoded"));
+    if form {
+        let fields = url::form_urlencoded::parse(bytes)
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        return Ok(Some(RequestBody::Form(fields)));
+    }
+    let json = serde_json::from_slice(bytes).map_err(Error::ServerInvalidJson)?;
+    Ok(Some(RequestBody::Json(json)))
+}
+
+/// Sends `/{endpoint}/{parameter}/...` to the endpoint with the method it expects
+async fn call(
+    state: AppState,
+    method: Method,
+    name: &str,
+    raw_values: Vec<String>,
+    headers: HeaderMap,
+    bytes: Bytes,
+) -> Result<Json<Value>, Error> {
+    let endpoint = endpoint::find(&state.endpoints, name)?;
+    if method != endpoint.method() {
+        return Err(Error::ServerMethodNotAllowed(
+            endpoint.name().to_string(),
+            endpoint.method().to_string(),
+        ));
+    }
+    let body = body(&headers, &bytes)?;
+    let api_response = crate::request(&state.config, endpoint, raw_values, body).await?;
+    Ok(Json(json!({
+        "date_time": timestamp(&api_response.date_time),
+        "url": api_response.url.as_str(),
+        "status": api_response.status.as_u16(),
+        "data": embed(&api_response.data),
+    })))
+}
+
+async fn call_without_parameters(
+    State(state): State<AppState>,
+    method: Method,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    bytes: Bytes,
+) -> Result<Json<Value>, Error> {
+    call(state, method, &name, Vec::new(), headers, bytes).await
+}
+
+async fn call_with_parameters(
+    State(state): State<AppState>,
+    method: Method,
+    Path((name, parameters)): Path<(String, String)>,
+    headers: HeaderMap,
+    bytes: Bytes,
+) -> Result<Json<Value>, Error> {
+    let raw_values = parameters
+        .trim_start_matches('/')
+        .split('/')
+        .map(str::to_string)
+        .collect();
+    call(state, method, &name, raw_values, headers, bytes).await
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    request_type: Option<String>,
+    #[serde(default = "default_limit")]
+    limit: u64,
+}
+
+fn default_limit() -> u64 {
+    20
+}
+
+async fn history(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Value>, Error> {
+    let responses =
+        crate::history(&state.config, query.request_type.as_deref(), query.limit).await?;
+    Ok(Json(Value::Array(
+        responses.iter().map(stored_json).collect(),
+    )))
+}
+
+async fn stored(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, Error> {
+    let stored = crate::stored(&state.config, id).await?;
+    Ok(Json(stored_json(&stored)))
+}
+
+fn router(config: Arc<Config>, endpoints: Vec<Arc<dyn Endpoint>>) -> Router {
+    let state = AppState {
+        config,
+        endpoints: Arc::new(endpoints),
+    };
+    Router::new()
+        .route("/history", get(history))
+        .route("/history/:id", get(stored))
+        .route("/:endpoint", any(call_without_parameters))
+        .route("/:endpoint/*parameters", any(call_with_parameters))
+        .with_state(state)
+}
+
+/// Binds the address the server listens on, e.g. `127.0.0.1:8080`
+pub async fn listen(address: &str) -> Result<TcpListener, Error> {
+    TcpListener::bind(address)
+        .await
+        .map_err(|e| Error::ServerBind(address.to_string(), e))
+}
+
+/// Serves every endpoint at `/{name}/{parameter}/...` and stored responses at `/history`
+/// until `shutdown` completes, finishing requests in flight
+pub async fn serve(
+    config: Arc<Config>,
+    endpoints: Vec<Arc<dyn Endpoint>>,
+    listener: TcpListener,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<(), Error> {
+    let address = listener
+        .local_addr()
+        .map(|address| address.to_string())
+        .unwrap_or_default();
+    tracing::info!("Listening on http://{address}");
+    axum::serve(listener, router(config, endpoints))
+        .with_graceful_shutdown(shutdown)
+        .await
+        .map_err(|e| Error::ServerIo(address, e))
+}
+
+#[tokio::test]
+async fn serve_test() {
+    use crate::{endpoint::builtin, test_support::stub_server};
+
+    let (api_root, _) = stub_server(|request, _| {
+        let path = request.split_whitespace().nth(1).unwrap_or_default();
+        (200, json!({ "path": path }).to_string())
+    })
+    .await;
+    let path = std::env::temp_dir().join("apicommand_serve_test.sqlite3");
+    let _ = std::fs::remove_file(&path);
+    let config = Config::builder()
+        .api_root(api_root.clone())
+        .api_key(None::<String>)
+        .db_path(path)
+        .build();
+    let listener = listen("127.0.0.1:0").await.unwrap();
+    let root = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(serve(
+        Arc::new(config),
+        builtin(),
+        listener,
+        std::future::pending(),
+    ));
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{root}/get/brand"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["data"]["path"], "/get/brand");
+
+    let response = client
+        .get(format!("{root}/specific/b/l/2/1"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("to_date"));
+
+    let response = client.get(format!("{root}/nope/1")).send().await.unwrap();
+    assert_eq!(response.status(), 404);
+    let response = client
+        .post(format!("{root}/get/brand"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 405);
+
+    let response = client
+        .get(format!("{root}/history?request_type=Get"))
+        .send()
+        .await
+        .unwrap();
+    let history: Value = response.json().await.unwrap();
+    assert_eq!(history.as_array().unwrap().len(), 1);
+    assert_eq!(history[0]["url"], format!("{api_root}/get/brand"));
+    let id = history[0]["id"].as_i64().unwrap();
+    let response = client
+        .get(format!("{root}/history/{id}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    // Decoded values can't add a query to the upstream request
+    let response = client
+        .get(format!("{root}/get/x%3Fadmin=1"))
+        .send()
+        .await
+        .unwrap();
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["data"]["path"], "/get/x%3Fadmin%3D1");
+}
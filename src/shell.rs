@@ -0,0 +1,175 @@
+use crate::{execute, subcommands};
+
+use apicommand::{configuration::Config, endpoint::Endpoint, known_values};
+use clap::Command;
+use color_eyre::eyre::Result;
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    history::DefaultHistory, validate::Validator, Context, Editor, Helper,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    sync::Arc,
+};
+
+/// Completes subcommands and the values of their parameters found in stored requests
+struct ShellHelper {
+    command: Command,
+    /// Values keyed by parameter name, e.g. the brand ids for `brand_id`
+    known: BTreeMap<String, BTreeSet<String>>,
+}
+impl ShellHelper {
+    /// Candidates for the word following `previous` words
+    fn candidates(&self, previous: &[&str], word: &str) -> Vec<String> {
+        let mut command = &self.command;
+        let mut position = 0;
+        for previous in previous {
+            match command.find_subcommand(previous) {
+                Some(subcommand) if position == 0 => command = subcommand,
+                // Options aren't completed, neither are their values
+                _ if previous.starts_with('-') => {}
+                _ => position += 1,
+            }
+        }
+
+        let candidates: Vec<&str> = if command.has_subcommands() && position == 0 {
+            command
+                .get_subcommands()
+                .flat_map(|subcommand| {
+                    std::iter::once(subcommand.get_name()).chain(subcommand.get_all_aliases())
+                })
+                .collect()
+        } else {
+            command
+                .get_positionals()
+                .nth(position)
+                .and_then(|positional| self.known.get(positional.get_id().as_str()))
+                .into_iter()
+                .flatten()
+                .map(String::as_str)
+                .collect()
+        };
+        candidates
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .map(str::to_string)
+            .collect()
+    }
+}
+impl Completer for ShellHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |index| index + 1);
+        let previous: Vec<&str> = line[..start].split_whitespace().collect();
+        Ok((start, self.candidates(&previous, &line[start..])))
+    }
+}
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+impl Highlighter for ShellHelper {}
+impl Validator for ShellHelper {}
+impl Helper for ShellHelper {}
+
+/// Lines entered in earlier sessions
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".apicommand_history"))
+}
+
+/// Subcommands accepted in the shell
+pub fn command(endpoints: &[Arc<dyn Endpoint>]) -> Command {
+    Command::new("apicommand")
+        .no_binary_name(true)
+        .subcommand_required(true)
+        .subcommands(subcommands(endpoints))
+        .subcommand(
+            Command::new("exit")
+                .visible_alias("quit")
+                .about("leave the shell"),
+        )
+}
+
+/// Reads and runs subcommands until `exit` or Ctrl-D, sharing the config with its client
+/// and database connection between them
+pub async fn run(config: Arc<Config>, endpoints: Vec<Arc<dyn Endpoint>>) -> Result<()> {
+    let command = command(&endpoints);
+    let mut editor: Editor<ShellHelper, DefaultHistory> = Editor::new()?;
+    let history = history_path();
+    if let Some(path) = &history {
+        // There is no history before the first session
+        let _ = editor.load_history(path);
+    }
+
+    loop {
+        // Refreshed as every command may store new values
+        let known = known_values(&config, &endpoints).await.unwrap_or_default();
+        editor.set_helper(Some(ShellHelper {
+            command: command.clone(),
+            known,
+        }));
+        let line = match editor.readline("apicommand> ") {
+            Ok(line) => line,
+            // Ctrl-C discards the line
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let Some(words) = shlex::split(&line) else {
+            eprintln!("error: unbalanced quotes");
+            continue;
+        };
+        if words.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line.as_str())?;
+
+        match command.clone().try_get_matches_from(words) {
+            Ok(matches) if matches.subcommand_name() == Some("exit") => break,
+            Ok(matches) => {
+                if let Err(e) = execute(&config, &endpoints, &matches).await {
+                    // The session goes on, so the message is enough
+                    eprintln!("Error: {e:#}");
+                }
+            }
+            // Also prints help and version
+            Err(e) => e.print()?,
+        }
+    }
+
+    if let Some(path) = &history {
+        editor.save_history(path)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn candidates_test() {
+    use apicommand::endpoint::builtin;
+
+    let helper = ShellHelper {
+        command: Command::new("apicommand").subcommands(subcommands(&builtin())),
+        known: BTreeMap::from([
+            (
+                "brand_id".to_string(),
+                BTreeSet::from(["acme".to_string(), "bolt".to_string()]),
+            ),
+            (
+                "location_id".to_string(),
+                BTreeSet::from(["berlin".to_string()]),
+            ),
+        ]),
+    };
+    assert_eq!(helper.candidates(&[], "la"), ["last_run"]);
+    assert_eq!(helper.candidates(&["get"], ""), ["acme", "bolt"]);
+    assert_eq!(helper.candidates(&["l", "acme"], "b"), ["berlin"]);
+    assert_eq!(helper.candidates(&["diff", "get"], "a"), ["acme"]);
+    assert!(helper.candidates(&["get", "acme"], "").is_empty());
+}
@@ -0,0 +1,129 @@
+use crate::{error::Error, secret::Secret};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::{
+    header::{HeaderName, HeaderValue},
+    Request,
+};
+use sha2::Sha256;
+use std::fmt::Debug;
+use typed_builder::TypedBuilder;
+
+/// Hook signing every request right before it is sent, after authentication
+pub trait RequestSigner: Debug + Send + Sync {
+    fn sign(&self, request: &mut Request) -> Result<(), Error>;
+}
+
+/// Encoding of the signature header value
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SignatureEncoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+/// Signs requests with an HMAC-SHA256 over a canonical string, sent with its timestamp
+#[derive(TypedBuilder, Debug)]
+pub struct HmacSigner {
+    #[builder(setter(into))]
+    key: Secret,
+    /// Template of the signed string with the placeholders `{method}`, `{path}` (including
+    /// the query), `{timestamp}` (unix seconds) and `{body}`
+    #[builder(default = "{method}\n{path}\n{timestamp}\n{body}".to_string())]
+    canonical: String,
+    #[builder(default = "X-Signature".to_string())]
+    signature_header: String,
+    #[builder(default = "X-Timestamp".to_string())]
+    timestamp_header: String,
+    #[builder(default)]
+    encoding: SignatureEncoding,
+}
+impl HmacSigner {
+    /// String signed for a request sent at `timestamp`
+    fn canonical_string(&self, request: &Request, timestamp: i64) -> String {
+        let url = request.url();
+        let path = match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_string(),
+        };
+        let body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map(String::from_utf8_lossy)
+            .unwrap_or_default();
+
+        self.canonical
+            .replace("{method}", request.method().as_str())
+            .replace("{path}", &path)
+            .replace("{timestamp}", &timestamp.to_string())
+            .replace("{body}", &body)
+    }
+
+    fn signature(&self, canonical: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.key.expose().as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(canonical.as_bytes());
+        let signature = mac.finalize().into_bytes();
+        match self.encoding {
+            SignatureEncoding::Hex => format!("{signature:x}"),
+            SignatureEncoding::Base64 => STANDARD.encode(signature),
+        }
+    }
+
+    fn sign_at(&self, request: &mut Request, timestamp: i64) -> Result<(), Error> {
+        let signature = self.signature(&self.canonical_string(request, timestamp));
+        let headers = request.headers_mut();
+        let name = |header: &String| {
+            HeaderName::from_bytes(header.as_bytes())
+                .map_err(|e| Error::NetworkInvalidHeaderName(header.clone(), e))
+        };
+        headers.insert(name(&self.timestamp_header)?, HeaderValue::from(timestamp));
+        let value = HeaderValue::from_str(&signature)
+            .map_err(|e| Error::NetworkInvalidHeaderValue(self.signature_header.clone(), e))?;
+        headers.insert(name(&self.signature_header)?, value);
+        Ok(())
+    }
+}
+impl RequestSigner for HmacSigner {
+    fn sign(&self, request: &mut Request) -> Result<(), Error> {
+        self.sign_at(request, Utc::now().timestamp())
+    }
+}
+
+#[test]
+fn hmac_signer_test() {
+    use reqwest::{Client, Url};
+
+    // RFC 4231 test case 2
+    let signer = HmacSigner::builder().key("Jefe".to_string()).build();
+    assert_eq!(
+        signer.signature("what do ya want for nothing?"),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+
+    let signer = HmacSigner::builder().key("secret".to_string()).build();
+    let url = Url::parse("http://localhost/get/brand?page=2").unwrap();
+    let mut request = Client::new().get(url).build().unwrap();
+    signer.sign_at(&mut request, 1_700_000_000).unwrap();
+    assert_eq!(request.headers()["X-Timestamp"], "1700000000");
+    assert_eq!(
+        request.headers()["X-Signature"],
+        "088abebb4fd045fe201c72b573bbacf17078059f5f50b18c9bc99beee17808f7"
+    );
+
+    let signer = HmacSigner::builder()
+        .key("secret".to_string())
+        .canonical("{timestamp}.{method}.{path}.{body}".to_string())
+        .signature_header("X-Gateway-Signature".to_string())
+        .encoding(SignatureEncoding::Base64)
+        .build();
+    let url = Url::parse("http://localhost/orders").unwrap();
+    let mut request = Client::new().post(url).body(r#"{"id":1}"#).build().unwrap();
+    signer.sign_at(&mut request, 1_700_000_000).unwrap();
+    assert_eq!(
+        request.headers()["X-Gateway-Signature"],
+        "zE3TLKuHOFCeV3Alm2hxNA/r4ZGkxvY7aLeuR2Jj8J0="
+    );
+}
@@ -0,0 +1,448 @@
+use apicommand::{
+    configuration::Config,
+    diff::{Change, ResponseDiff},
+    diff_previous,
+    endpoint::Endpoint,
+    history, reissue, StoredResponse,
+};
+use color_eyre::eyre::Result;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style, Stylize},
+    text::Line,
+    widgets::{Block, Paragraph, Row, Table, TableState, Wrap},
+    DefaultTerminal, Frame,
+};
+use std::{collections::BTreeSet, sync::Arc};
+
+/// Responses listed by their outcome
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutcomeFilter {
+    All,
+    Success,
+    /// Any outcome but `Success`, e.g. `SchemaViolation`
+    Failure,
+}
+impl OutcomeFilter {
+    fn next(self) -> Self {
+        match self {
+            OutcomeFilter::All => OutcomeFilter::Success,
+            OutcomeFilter::Success => OutcomeFilter::Failure,
+            OutcomeFilter::Failure => OutcomeFilter::All,
+        }
+    }
+    fn matches(self, outcome: &str) -> bool {
+        match self {
+            OutcomeFilter::All => true,
+            OutcomeFilter::Success => outcome == "Success",
+            OutcomeFilter::Failure => outcome != "Success",
+        }
+    }
+}
+
+/// Content of the detail pane
+enum Detail {
+    Body,
+    Diff(Box<ResponseDiff>),
+}
+
+/// Work a key asks for that needs the API or the database
+#[derive(Debug, PartialEq)]
+enum Action {
+    None,
+    Quit,
+    Reload,
+    Reissue,
+    Diff,
+}
+
+struct App {
+    config: Arc<Config>,
+    endpoints: Vec<Arc<dyn Endpoint>>,
+    limit: u64,
+    /// Stored names of the endpoints, cycled through by the request type filter
+    request_types: Vec<String>,
+    /// Index into `request_types`, `None` lists all
+    request_type: Option<usize>,
+    outcome: OutcomeFilter,
+    /// Matched against urls and bodies, ignoring case
+    text: String,
+    editing: bool,
+    /// Latest stored responses of the request type, newest first
+    responses: Vec<StoredResponse>,
+    table: TableState,
+    detail: Detail,
+    scroll: u16,
+    status: String,
+}
+impl App {
+    fn new(config: Arc<Config>, endpoints: Vec<Arc<dyn Endpoint>>, limit: u64) -> Self {
+        let request_types = endpoints
+            .iter()
+            .map(|endpoint| endpoint.stored_name().to_string())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        Self {
+            config,
+            endpoints,
+            limit,
+            request_types,
+            request_type: None,
+            outcome: OutcomeFilter::All,
+            text: String::new(),
+            editing: false,
+            responses: Vec::new(),
+            table: TableState::default(),
+            detail: Detail::Body,
+            scroll: 0,
+            status: String::new(),
+        }
+    }
+
+    /// Loaded responses passing the outcome and text filters
+    fn visible(&self) -> Vec<&StoredResponse> {
+        let text = self.text.to_lowercase();
+        self.responses
+            .iter()
+            .filter(|stored| self.outcome.matches(&stored.outcome))
+            .filter(|stored| {
+                text.is_empty()
+                    || stored.url.to_lowercase().contains(&text)
+                    || stored.data.to_lowercase().contains(&text)
+            })
+            .collect()
+    }
+
+    fn selected(&self) -> Option<&StoredResponse> {
+        self.visible().get(self.table.selected()?).copied()
+    }
+
+    /// Selects the row `offset` rows away, showing its body
+    fn select(&mut self, offset: isize) {
+        let count = self.visible().len();
+        let selected = match (self.table.selected(), count) {
+            (_, 0) => None,
+            (None, _) => Some(0),
+            (Some(index), _) => Some(index.saturating_add_signed(offset).min(count - 1)),
+        };
+        self.table.select(selected);
+        self.detail = Detail::Body;
+        self.scroll = 0;
+    }
+
+    async fn load(&mut self) {
+        let request_type = self
+            .request_type
+            .map(|index| self.request_types[index].as_str());
+        match history(&self.config, request_type, self.limit).await {
+            Ok(responses) => self.responses = responses,
+            Err(e) => self.status = format!("Error: {}", e.message()),
+        }
+        self.table.select(None);
+        self.select(0);
+    }
+
+    async fn reissue(&mut self) {
+        let Some(stored) = self.selected() else {
+            return;
+        };
+        self.status = match reissue(&self.config, &self.endpoints, stored).await {
+            Ok(api_response) => format!("{} {}", api_response.status, api_response.url),
+            // Responses violating their schema are stored nonetheless
+            Err(e) => format!("Error: {}", e.message()),
+        };
+        self.load().await;
+    }
+
+    async fn diff(&mut self) {
+        let Some(stored) = self.selected().cloned() else {
+            return;
+        };
+        match diff_previous(&self.config, stored).await {
+            Ok(response_diff) => {
+                self.detail = Detail::Diff(Box::new(response_diff));
+                self.scroll = 0;
+            }
+            Err(e) => self.status = format!("Error: {}", e.message()),
+        }
+    }
+
+    /// Applies a key press, returning the work it asks for
+    fn handle(&mut self, key: KeyCode) -> Action {
+        self.status.clear();
+        if self.editing {
+            match key {
+                KeyCode::Char(c) => self.text.push(c),
+                KeyCode::Backspace => {
+                    self.text.pop();
+                }
+                KeyCode::Enter | KeyCode::Esc => self.editing = false,
+                _ => {}
+            }
+            self.table.select(None);
+            self.select(0);
+            return Action::None;
+        }
+
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+            KeyCode::Down | KeyCode::Char('j') => self.select(1),
+            KeyCode::Up | KeyCode::Char('k') => self.select(-1),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::Enter => {
+                self.detail = Detail::Body;
+                self.scroll = 0;
+            }
+            KeyCode::Char('t') => {
+                self.request_type = match self.request_type {
+                    None if !self.request_types.is_empty() => Some(0),
+                    Some(index) if index + 1 < self.request_types.len() => Some(index + 1),
+                    _ => None,
+                };
+                return Action::Reload;
+            }
+            KeyCode::Char('o') => {
+                self.outcome = self.outcome.next();
+                self.table.select(None);
+                self.select(0);
+            }
+            KeyCode::Char('/') => self.editing = true,
+            KeyCode::Char('r') => return Action::Reissue,
+            KeyCode::Char('d') => return Action::Diff,
+            KeyCode::F(5) => return Action::Reload,
+            _ => {}
+        }
+        Action::None
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [filters, main, status] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [list, detail] =
+            Layout::horizontal([Constraint::Percentage(45), Constraint::Percentage(55)])
+                .areas(main);
+
+        let visible = self.visible();
+        let request_type = self
+            .request_type
+            .map_or("All", |index| self.request_types[index].as_str());
+        let cursor = if self.editing { "_" } else { "" };
+        frame.render_widget(
+            Line::from(format!(
+                "Type: {request_type} | Outcome: {:?} | Filter: {}{cursor} | {} of {} responses",
+                self.outcome,
+                self.text,
+                visible.len(),
+                self.responses.len()
+            )),
+            filters,
+        );
+
+        let rows = visible.iter().map(|stored| {
+            let color = if stored.outcome == "Success" {
+                Color::Green
+            } else {
+                Color::Red
+            };
+            Row::new([
+                format!("#{}", stored.id).into(),
+                stored
+                    .date_time
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+                    .into(),
+                stored.request_type.clone().into(),
+                stored.outcome.clone().fg(color),
+                stored.url.clone().into(),
+            ])
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(7),
+                Constraint::Length(19),
+                Constraint::Length(10),
+                Constraint::Length(15),
+                Constraint::Min(10),
+            ],
+        )
+        .header(Row::new(["Id", "Date time (UTC)", "Type", "Outcome", "Url"]).bold())
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+        .block(Block::bordered().title(" Responses "));
+
+        let (title, lines) = match (&self.detail, self.selected()) {
+            (_, None) => (" Response ".to_string(), Vec::new()),
+            (Detail::Body, Some(stored)) => (format!(" #{} ", stored.id), body_lines(stored)),
+            (Detail::Diff(response_diff), Some(_)) => (
+                format!(
+                    " Diff #{} -> #{} ",
+                    response_diff.old.id, response_diff.new.id
+                ),
+                diff_lines(response_diff),
+            ),
+        };
+        frame.render_stateful_widget(table, list, &mut self.table);
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(Block::bordered().title(title))
+                .wrap(Wrap { trim: false })
+                .scroll((self.scroll, 0)),
+            detail,
+        );
+
+        let line = if self.status.is_empty() {
+            Line::from(
+                "↑↓ select  Enter body  d diff previous  r re-issue path and unredacted body  \
+                 t type  o outcome  / filter  PgUp/PgDn scroll  F5 reload  q quit",
+            )
+            .dim()
+        } else {
+            Line::from(self.status.as_str())
+        };
+        frame.render_widget(line, status);
+    }
+}
+
+/// Body pretty-printed if it is JSON
+fn pretty(data: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(data)
+        .and_then(|value| serde_json::to_string_pretty(&value))
+        .unwrap_or_else(|_| data.to_string())
+}
+
+fn body_lines(stored: &StoredResponse) -> Vec<Line<'static>> {
+    let mut lines = vec![
+        Line::from(stored.url.clone()),
+        Line::from(format!("{} {}", stored.date_time, stored.outcome)),
+    ];
+    if let Some(request_body) = &stored.request_body {
+        lines.push(Line::from("Request body:").bold());
+        lines.extend(
+            pretty(request_body)
+                .lines()
+                .map(|line| Line::from(line.to_string())),
+        );
+    }
+    lines.push(Line::from("Response body:").bold());
+    lines.extend(
+        pretty(&stored.data)
+            .lines()
+            .map(|line| Line::from(line.to_string())),
+    );
+    lines
+}
+
+fn diff_lines(response_diff: &ResponseDiff) -> Vec<Line<'static>> {
+    let ResponseDiff { old, new, changes } = response_diff;
+    let mut lines = vec![
+        Line::from(format!("--- #{} {} {}", old.id, old.date_time, old.url)),
+        Line::from(format!("+++ #{} {} {}", new.id, new.date_time, new.url)),
+    ];
+    if changes.is_empty() {
+        lines.push(Line::from("No changes").dim());
+    }
+    lines.extend(changes.iter().map(|change| {
+        let color = match change {
+            Change::Added(..) => Color::Green,
+            Change::Removed(..) => Color::Red,
+            Change::Changed(..) => Color::Yellow,
+        };
+        Line::from(change.to_string()).fg(color)
+    }));
+    lines
+}
+
+async fn event_loop(terminal: &mut DefaultTerminal, app: &mut App) -> Result<()> {
+    app.load().await;
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        // Reading blocks until the next event
+        let Event::Key(key) = tokio::task::spawn_blocking(event::read).await?? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match app.handle(key.code) {
+            Action::None => {}
+            Action::Quit => return Ok(()),
+            Action::Reload => app.load().await,
+            Action::Reissue => {
+                app.status = "Sending request...".to_string();
+                terminal.draw(|frame| app.draw(frame))?;
+                app.reissue().await;
+            }
+            Action::Diff => app.diff().await,
+        }
+    }
+}
+
+/// Browses the latest `limit` stored responses until `q` is pressed
+pub async fn run(config: Arc<Config>, endpoints: Vec<Arc<dyn Endpoint>>, limit: u64) -> Result<()> {
+    let mut app = App::new(config, endpoints, limit);
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut app).await;
+    ratatui::restore();
+    result
+}
+
+#[test]
+fn handle_test() {
+    use apicommand::endpoint::builtin;
+    use chrono::Utc;
+
+    let config = Config::builder()
+        .api_root("http://localhost".to_string())
+        .api_key(None::<String>)
+        .db_path(std::path::PathBuf::from("test.sqlite3"))
+        .build();
+    let mut app = App::new(Arc::new(config), builtin(), 10);
+    let stored = |id, outcome: &str, data: &str| StoredResponse {
+        id,
+        date_time: Utc::now(),
+        request_type: "Get".to_string(),
+        url: format!("http://localhost/get/brand{id}"),
+        data: data.to_string(),
+        outcome: outcome.to_string(),
+        request_body: None,
+    };
+    app.responses = vec![
+        stored(3, "Success", r#"{"store": "X123"}"#),
+        stored(2, "SchemaViolation", "{}"),
+        stored(1, "Success", r#"{"store": "Y456"}"#),
+    ];
+    app.select(0);
+    assert_eq!(app.selected().unwrap().id, 3);
+    app.handle(KeyCode::Down);
+    app.handle(KeyCode::Down);
+    app.handle(KeyCode::Down);
+    assert_eq!(app.selected().unwrap().id, 1);
+
+    app.handle(KeyCode::Char('o'));
+    assert_eq!(app.visible().len(), 2);
+    app.handle(KeyCode::Char('o'));
+    assert_eq!(app.selected().unwrap().id, 2);
+    app.handle(KeyCode::Char('o'));
+
+    app.handle(KeyCode::Char('/'));
+    for c in "x12".chars() {
+        app.handle(KeyCode::Char(c));
+    }
+    app.handle(KeyCode::Enter);
+    assert_eq!(app.text, "x12");
+    assert_eq!(app.visible().len(), 1);
+    assert_eq!(app.selected().unwrap().id, 3);
+
+    assert_eq!(app.handle(KeyCode::Char('d')), Action::Diff);
+    assert_eq!(app.handle(KeyCode::Char('r')), Action::Reissue);
+    assert_eq!(app.handle(KeyCode::Char('t')), Action::Reload);
+    assert_eq!(app.request_types[app.request_type.unwrap()], "Get");
+    assert_eq!(app.handle(KeyCode::Char('q')), Action::Quit);
+}